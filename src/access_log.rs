@@ -0,0 +1,109 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::storage::{AsBuffer, BodySize, Kawa, StatusLine, Store, Version};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+fn version_str(version: Version) -> &'static str {
+    match version {
+        Version::V10 => "HTTP/1.0",
+        Version::V11 => "HTTP/1.1",
+        Version::V20 => "HTTP/2.0",
+        Version::Unknown => "-",
+    }
+}
+
+fn store_str<'a>(store: &'a Store, buf: &'a [u8]) -> &'a str {
+    match store.data_opt(buf) {
+        Some(data) if !data.is_empty() => std::str::from_utf8(data).unwrap_or("-"),
+        _ => "-",
+    }
+}
+
+/// Format a time as "10/Oct/2000:13:55:36 +0000" (always in UTC)
+fn format_time(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // civil date from a number of days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    )
+}
+
+/// Build an Apache common log format line from a request and its response, timestamped now.
+///
+/// note: the status lines are read from the detached blocks, so this must be called before the
+/// Kawas are prepared.
+pub fn common_log_line<T: AsBuffer, U: AsBuffer>(
+    request: &Kawa<T>,
+    response: &Kawa<U>,
+    remote: &str,
+) -> String {
+    common_log_line_at(request, response, remote, SystemTime::now())
+}
+
+/// Build an Apache common log format line from a request and its response, timestamped at `time`:
+///
+/// `remote - - [10/Oct/2000:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 2326`
+///
+/// Missing fields are replaced by "-", as is the size of a body without content-length.
+pub fn common_log_line_at<T: AsBuffer, U: AsBuffer>(
+    request: &Kawa<T>,
+    response: &Kawa<U>,
+    remote: &str,
+    time: SystemTime,
+) -> String {
+    let request_line = match &request.detached.status_line {
+        StatusLine::Request {
+            version,
+            method,
+            uri,
+            ..
+        } => {
+            let buf = request.storage.buffer();
+            format!(
+                "{} {} {}",
+                store_str(method, buf),
+                store_str(uri, buf),
+                version_str(*version)
+            )
+        }
+        _ => "-".to_string(),
+    };
+    let code = match &response.detached.status_line {
+        StatusLine::Response { code, .. } => code.to_string(),
+        _ => "-".to_string(),
+    };
+    let size = match response.body_size {
+        BodySize::Length(length) if length > 0 => length.to_string(),
+        _ => "-".to_string(),
+    };
+    format!(
+        "{} - - [{}] \"{}\" {} {}",
+        if remote.is_empty() { "-" } else { remote },
+        format_time(time),
+        request_line,
+        code,
+        size
+    )
+}
@@ -1,7 +1,13 @@
+pub mod access_log;
 mod protocol;
 mod storage;
 
-pub use protocol::{h1, h2};
+pub use protocol::{
+    h1,
+    h1::complete::{parse_complete, ParseError, ParsedMessage, ParsedStatusLine},
+    h1::parser::peek_method,
+    h2, ws,
+};
 pub use storage::*;
 
 pub struct SliceBuffer<'a>(pub &'a mut [u8]);
@@ -0,0 +1,191 @@
+use std::borrow::Cow;
+
+use crate::{
+    protocol::{
+        h1::parser::{parse, NoCallbacks},
+        utils::compare_no_case,
+    },
+    storage::{
+        Block, BodySize, Buffer, Chunk, Flags, Kawa, Kind, Pair, ParsingErrorKind, ParsingPhase,
+        ParsingPhaseMarker, StatusLine, Store, Version,
+    },
+};
+
+/// Error returned by parse_complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input ends before the end of the message
+    Incomplete,
+    /// The input holds more than one message, the value is the number of unparsed bytes
+    TrailingData(usize),
+    /// The message is invalid, the marker is the phase in which the parsing failed
+    Invalid {
+        marker: ParsingPhaseMarker,
+        kind: ParsingErrorKind,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedStatusLine<'a> {
+    Request {
+        method: &'a [u8],
+        authority: &'a [u8],
+        path: &'a [u8],
+        uri: &'a [u8],
+    },
+    Response {
+        code: u16,
+        status: &'a [u8],
+        reason: &'a [u8],
+    },
+}
+
+/// View of a complete H1 message, borrowed from the input given to parse_complete.
+///
+/// The Host header is elided, it is the authority of the status line. Header values are
+/// borrowed unless the parser had to rewrite them (unfolded obs-fold lines). The body is made
+/// of the chunks of the message, without the chunk headers.
+#[derive(Debug, Clone)]
+pub struct ParsedMessage<'a> {
+    pub kind: Kind,
+    pub version: Version,
+    pub status_line: ParsedStatusLine<'a>,
+    pub headers: Vec<(&'a [u8], Cow<'a, [u8]>)>,
+    pub cookies: Vec<(&'a [u8], &'a [u8])>,
+    pub body: Vec<&'a [u8]>,
+    pub trailers: Vec<(&'a [u8], Cow<'a, [u8]>)>,
+}
+
+impl<'a> ParsedMessage<'a> {
+    /// Returns the value of the first header with the given name, compared case insensitively
+    pub fn header(&self, name: &[u8]) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(key, _)| compare_no_case(key, name))
+            .map(|(_, val)| val.as_ref())
+    }
+}
+
+/// Parse a message entirely held in memory, this wraps the H1 parser for the non streaming use
+/// case. The input is copied in a Kawa buffer, the parsed view borrows from the input.
+///
+/// A message whose body is delimited by the end of the connection ends with the input.
+pub fn parse_complete(kind: Kind, bytes: &[u8]) -> Result<ParsedMessage<'_>, ParseError> {
+    let mut kawa = Kawa::new(kind, Buffer::new(bytes.to_vec()));
+    kawa.storage.fill(bytes.len());
+    parse(&mut kawa, &mut NoCallbacks);
+
+    match kawa.parsing_phase {
+        ParsingPhase::Error { marker, kind } => return Err(ParseError::Invalid { marker, kind }),
+        ParsingPhase::Terminated => {}
+        ParsingPhase::Body if kawa.body_size == BodySize::Empty => {}
+        _ => return Err(ParseError::Incomplete),
+    }
+    let unparsed = kawa.storage.unparsed_data().len();
+    if unparsed > 0 {
+        return Err(ParseError::TrailingData(unparsed));
+    }
+
+    let buf = kawa.storage.buffer();
+    let version = kawa.detached.status_line.version();
+    let status_line = match &kawa.detached.status_line {
+        StatusLine::Request {
+            method,
+            authority,
+            path,
+            uri,
+            ..
+        } => ParsedStatusLine::Request {
+            method: borrow(bytes, method),
+            authority: borrow(bytes, authority),
+            path: borrow(bytes, path),
+            uri: borrow(bytes, uri),
+        },
+        StatusLine::Response {
+            code,
+            status,
+            reason,
+            ..
+        } => ParsedStatusLine::Response {
+            code: *code,
+            status: borrow(bytes, status),
+            reason: borrow(bytes, reason),
+        },
+        StatusLine::Unknown => unreachable!(),
+    };
+    let cookies = kawa
+        .detached
+        .jar
+        .iter()
+        .map(|Pair { key, val }| (borrow(bytes, key), borrow(bytes, val)))
+        .collect();
+
+    let mut headers = Vec::new();
+    let mut trailers = Vec::new();
+    let mut body = Vec::new();
+    let mut in_trailers = false;
+    for block in &kawa.blocks {
+        match block {
+            Block::Header(Pair {
+                key: Store::Empty, ..
+            }) => {}
+            Block::Header(Pair { key, val }) => {
+                let section = if in_trailers {
+                    &mut trailers
+                } else {
+                    &mut headers
+                };
+                section.push((borrow(bytes, key), borrow_value(bytes, buf, val)));
+            }
+            Block::Chunk(Chunk { data }) => {
+                // a message without body can hold an empty Chunk
+                let data = borrow(bytes, data);
+                if !data.is_empty() {
+                    body.push(data);
+                }
+            }
+            Block::Flags(Flags { end_header, .. }) if *end_header => in_trailers = true,
+            _ => {}
+        }
+    }
+
+    Ok(ParsedMessage {
+        kind,
+        version,
+        status_line,
+        headers,
+        cookies,
+        body,
+        trailers,
+    })
+}
+
+/// The Kawa buffer is a copy of the input, a Slice has the same range in both
+fn borrow<'a>(bytes: &'a [u8], store: &Store) -> &'a [u8] {
+    match store {
+        Store::Empty => &[],
+        Store::Slice(slice) | Store::Detached(slice) => {
+            let start = slice.start as usize;
+            &bytes[start..start + slice.len()]
+        }
+        Store::Static(data) => data,
+        _ => unreachable!("the H1 parser only allocates header values"),
+    }
+}
+
+/// Header values may have been modified in place or allocated by the parser
+fn borrow_value<'a>(bytes: &'a [u8], buf: &[u8], store: &Store) -> Cow<'a, [u8]> {
+    match store {
+        Store::Slice(_) | Store::Detached(_) => {
+            let data = store.data(buf);
+            let borrowed = borrow(bytes, store);
+            if data == borrowed {
+                Cow::Borrowed(borrowed)
+            } else {
+                Cow::Owned(data.to_vec())
+            }
+        }
+        Store::Empty | Store::Static(_) => Cow::Borrowed(borrow(bytes, store)),
+        _ => Cow::Owned(store.data(buf).to_vec()),
+    }
+}
@@ -1,3 +1,7 @@
+#[cfg(feature = "flate2")]
+use std::io::Write;
+use std::mem;
+
 use crate::storage::{
     AsBuffer, Block, BlockConverter, Chunk, ChunkHeader, Flags, Kawa, OutBlock, Pair, StatusLine,
     Store, Version,
@@ -5,6 +9,113 @@ use crate::storage::{
 
 pub struct H1BlockConverter;
 
+/// H1 converter for clients that don't support chunked transfer: the body is buffered until
+/// it ends, then sent with a Content-Length. Chunk headers and trailers are dropped, the
+/// Transfer-Encoding and Content-Length headers are replaced by the computed Content-Length.
+///
+/// note: the end of the header section is held back with the body, the whole body is copied in
+/// a single allocated Store pushed by finalize once the message is entirely converted.
+#[derive(Debug, Clone, Default)]
+pub struct ContentLengthBlockConverter {
+    body: Vec<u8>,
+    in_trailers: bool,
+    /// the message can't have a body or a Content-Length (1xx, 204 and 304 responses)
+    bodyless: bool,
+    ended: bool,
+    /// the header section end and the body are pushed, the trailers are still dropped until the
+    /// end of the stream
+    flushed: bool,
+    stream_ended: bool,
+}
+
+/// Streaming body encoder used by CompressionBlockConverter, typically a gzip encoder. The
+/// `flate2` feature provides GzipEncoder, other codings can be plugged by implementing it.
+pub trait BodyEncoder {
+    /// Content coding announced in the Content-Encoding header (e.g. `gzip`)
+    fn coding(&self) -> &'static [u8];
+    /// Feed a part of the body, returns the encoded bytes available so far
+    fn encode(&mut self, data: &[u8]) -> Vec<u8>;
+    /// End the encoded stream, returns the remaining encoded bytes
+    fn finish(&mut self) -> Vec<u8>;
+}
+
+/// H1 converter encoding the body on the fly with a BodyEncoder. The final size is unknown so
+/// the body is always sent chunked: the Content-Length and Transfer-Encoding headers are
+/// replaced by `Transfer-Encoding: chunked` and a Content-Encoding header. Each Chunk producing
+/// encoded bytes is sent in its own chunk, the encoder is finished at the end of the body and
+/// the trailers are kept. Messages without body are left unencoded.
+///
+/// note: the converter handles a single message, the body should not be already encoded.
+#[derive(Debug, Clone, Default)]
+pub struct CompressionBlockConverter<E: BodyEncoder> {
+    pub encoder: E,
+    in_trailers: bool,
+    /// the message can't have a body or a Content-Length (1xx, 204 and 304 responses)
+    bodyless: bool,
+}
+
+/// gzip BodyEncoder, a flate2 GzEncoder writing in a Vec drained at each call
+#[cfg(feature = "flate2")]
+#[derive(Debug)]
+pub struct GzipEncoder(flate2::write::GzEncoder<Vec<u8>>);
+
+/// H1 converter compressing the body with gzip
+#[cfg(feature = "flate2")]
+pub type GzipConverter = CompressionBlockConverter<GzipEncoder>;
+
+#[cfg(feature = "flate2")]
+impl GzipEncoder {
+    pub fn new(level: flate2::Compression) -> Self {
+        Self(flate2::write::GzEncoder::new(Vec::new(), level))
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl Default for GzipEncoder {
+    fn default() -> Self {
+        Self::new(flate2::Compression::default())
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl BodyEncoder for GzipEncoder {
+    fn coding(&self) -> &'static [u8] {
+        b"gzip"
+    }
+    fn encode(&mut self, data: &[u8]) -> Vec<u8> {
+        // writing in a Vec can't fail
+        self.0.write_all(data).expect("write in a Vec");
+        mem::take(self.0.get_mut())
+    }
+    fn finish(&mut self) -> Vec<u8> {
+        self.0.try_finish().expect("write in a Vec");
+        mem::take(self.0.get_mut())
+    }
+}
+
+impl<E: BodyEncoder> CompressionBlockConverter<E> {
+    pub fn new(encoder: E) -> Self {
+        Self {
+            encoder,
+            in_trailers: false,
+            bodyless: false,
+        }
+    }
+
+    fn push_chunk<T: AsBuffer>(kawa: &mut Kawa<T>, encoded: Vec<u8>) {
+        if encoded.is_empty() {
+            return;
+        }
+        kawa.push_out(Store::from_string(format!("{:x}\r\n", encoded.len())));
+        kawa.push_out(Store::from_vec(encoded));
+        kawa.push_out(Store::Static(b"\r\n"));
+    }
+}
+
+fn is_framing_header(buf: &[u8], key: &Store) -> bool {
+    key.equals_no_case(buf, b"transfer-encoding") || key.equals_no_case(buf, b"content-length")
+}
+
 impl Version {
     fn as_store(&self) -> Store {
         match self {
@@ -51,10 +162,8 @@ impl<T: AsBuffer> BlockConverter<T> for H1BlockConverter {
                 StatusLine::Unknown => unreachable!(),
             },
             Block::Cookies => {
-                if kawa.detached.jar.is_empty() {
-                    return true;
-                }
-                kawa.push_out(Store::Static(b"Cookie: "));
+                // all the crumbs are merged in a single header, whether they come from one or
+                // several cookie headers, the header is omitted if every crumb is elided
                 let mut first = true;
                 for cookie in kawa
                     .detached
@@ -62,15 +171,19 @@ impl<T: AsBuffer> BlockConverter<T> for H1BlockConverter {
                     .drain(..)
                     .filter(|cookie| !cookie.is_elided())
                 {
-                    if !first {
-                        kawa.out.push_back(OutBlock::Store(Store::Static(b"; ")));
-                    }
+                    let separator = if first { &b"Cookie: "[..] } else { b"; " };
                     first = false;
-                    kawa.out.push_back(OutBlock::Store(cookie.key));
-                    kawa.out.push_back(OutBlock::Store(Store::Static(b"=")));
+                    kawa.out
+                        .push_back(OutBlock::Store(Store::Static(separator)));
+                    if cookie.has_crumb_separator() {
+                        kawa.out.push_back(OutBlock::Store(cookie.key));
+                        kawa.out.push_back(OutBlock::Store(Store::Static(b"=")));
+                    }
                     kawa.out.push_back(OutBlock::Store(cookie.val));
                 }
-                kawa.push_out(Store::Static(b"\r\n"));
+                if !first {
+                    kawa.push_out(Store::Static(b"\r\n"));
+                }
             }
             Block::Header(Pair {
                 key: Store::Empty, ..
@@ -83,8 +196,23 @@ impl<T: AsBuffer> BlockConverter<T> for H1BlockConverter {
                 kawa.push_out(val);
                 kawa.push_out(Store::Static(b"\r\n"));
             }
-            Block::ChunkHeader(ChunkHeader { length }) => {
+            Block::ChunkHeader(ChunkHeader { length, extensions }) => {
+                let last = length
+                    .data(kawa.storage.buffer())
+                    .iter()
+                    .all(|c| *c == b'0');
+                if last {
+                    // the last chunk is written here with its extensions, not by the Flags
+                    // ending the body that follows it
+                    if let Some(Block::Flags(flags)) = kawa.blocks.front_mut() {
+                        flags.end_body = false;
+                    }
+                }
                 kawa.push_out(length);
+                if !extensions.is_empty() {
+                    kawa.push_out(Store::Static(b";"));
+                    kawa.push_out(extensions);
+                }
                 kawa.push_out(Store::Static(b"\r\n"));
             }
             Block::Chunk(Chunk { data }) => {
@@ -107,3 +235,115 @@ impl<T: AsBuffer> BlockConverter<T> for H1BlockConverter {
         true
     }
 }
+
+impl<T: AsBuffer> BlockConverter<T> for ContentLengthBlockConverter {
+    fn call(&mut self, block: Block, kawa: &mut Kawa<T>) -> bool {
+        match block {
+            Block::StatusLine => {
+                if let StatusLine::Response { code, .. } = kawa.detached.status_line {
+                    self.bodyless = code == 204 || code == 304 || (100..200).contains(&code);
+                }
+                H1BlockConverter.call(block, kawa);
+            }
+            Block::Header(Pair { key, .. })
+                if self.in_trailers || is_framing_header(kawa.storage.buffer(), &key) => {}
+            Block::ChunkHeader(_) => {}
+            Block::Chunk(Chunk { data }) => {
+                if let Some(data) = data.data_opt(kawa.storage.buffer()) {
+                    self.body.extend_from_slice(data);
+                }
+            }
+            Block::Flags(Flags {
+                end_header,
+                end_body,
+                end_stream,
+                ..
+            }) => {
+                self.in_trailers |= end_header;
+                self.ended |= end_body || end_stream;
+                self.stream_ended |= end_stream;
+            }
+            block => {
+                H1BlockConverter.call(block, kawa);
+            }
+        }
+        true
+    }
+
+    fn finalize(&mut self, kawa: &mut Kawa<T>) {
+        if self.ended && !self.flushed {
+            if !self.bodyless {
+                kawa.push_out(Store::Static(b"Content-Length: "));
+                kawa.push_out(Store::from_string(self.body.len().to_string()));
+                kawa.push_out(Store::Static(b"\r\n"));
+            }
+            kawa.push_out(Store::Static(b"\r\n"));
+            if !self.body.is_empty() {
+                kawa.push_out(Store::from_vec(mem::take(&mut self.body)));
+            }
+            self.flushed = true;
+        }
+        if self.stream_ended {
+            *self = Self::default();
+        }
+    }
+}
+
+impl<T: AsBuffer, E: BodyEncoder> BlockConverter<T> for CompressionBlockConverter<E> {
+    fn call(&mut self, block: Block, kawa: &mut Kawa<T>) -> bool {
+        match block {
+            Block::StatusLine => {
+                if let StatusLine::Response { code, .. } = kawa.detached.status_line {
+                    self.bodyless = code == 204 || code == 304 || (100..200).contains(&code);
+                }
+                H1BlockConverter.call(block, kawa);
+            }
+            Block::Header(Pair { key, .. })
+                if !self.in_trailers && is_framing_header(kawa.storage.buffer(), &key) => {}
+            Block::ChunkHeader(_) => {}
+            Block::Chunk(Chunk { data }) => {
+                if let Some(data) = data.data_opt(kawa.storage.buffer()) {
+                    let encoded = self.encoder.encode(data);
+                    Self::push_chunk(kawa, encoded);
+                }
+            }
+            Block::Flags(Flags {
+                end_header,
+                end_body,
+                end_stream,
+                ..
+            }) => {
+                if end_header && !self.in_trailers {
+                    self.in_trailers = true;
+                    if !end_stream {
+                        kawa.push_out(Store::Static(
+                            b"Transfer-Encoding: chunked\r\nContent-Encoding: ",
+                        ));
+                        kawa.push_out(Store::Static(self.encoder.coding()));
+                        kawa.push_out(Store::Static(b"\r\n"));
+                    } else if !self.bodyless {
+                        kawa.push_out(Store::Static(b"Content-Length: 0\r\n"));
+                    }
+                    kawa.push_out(Store::Static(b"\r\n"));
+                    return true;
+                }
+                if end_body {
+                    let encoded = self.encoder.finish();
+                    Self::push_chunk(kawa, encoded);
+                    kawa.push_out(Store::Static(b"0\r\n"));
+                    if !kawa.is_streaming() {
+                        // no trailer section follows a body with a Content-Length
+                        kawa.push_out(Store::Static(b"\r\n"));
+                    }
+                }
+                if end_header {
+                    kawa.push_out(Store::Static(b"\r\n"));
+                }
+            }
+            block => {
+                H1BlockConverter.call(block, kawa);
+            }
+        }
+        true
+    }
+}
@@ -1,5 +1,12 @@
+pub mod complete;
 pub mod converter;
 pub mod parser;
 
 pub use converter::H1BlockConverter as BlockConverter;
-pub use parser::{parse, NoCallbacks, ParserCallbacks};
+pub use converter::{BodyEncoder, CompressionBlockConverter, ContentLengthBlockConverter};
+#[cfg(feature = "flate2")]
+pub use converter::{GzipConverter, GzipEncoder};
+pub use parser::{
+    end_of_input, parse, parse_auto, parse_until, peek_method, HashingCallbacks, NoCallbacks,
+    ParserCallbacks,
+};
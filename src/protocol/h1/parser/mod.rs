@@ -1,22 +1,28 @@
 use std::cmp::min;
+use std::hash::Hasher;
 use std::mem;
 
+#[cfg(feature = "tolerant-parsing")]
+use nom::character::is_space;
 use nom::{error::Error as NomError, Err as NomErr, Offset, ParseTo};
 
 /// Primitives used to parse http using nom and simd optimization when applicable
 pub mod primitives;
 
+#[cfg(feature = "tolerant-parsing")]
+use crate::protocol::h1::parser::primitives::parse_obs_fold;
 use crate::{
     protocol::{
         h1::parser::primitives::{
-            crlf, parse_chunk_header, parse_header, parse_header_or_cookie, parse_request_line,
-            parse_response_line, parse_single_crumb, parse_url,
+            crlf, is_space_before_colon, parse_chunk_header, parse_header, parse_header_or_cookie,
+            parse_request_line, parse_response_line, parse_single_crumb, parse_url, tchar,
         },
-        utils::compare_no_case,
+        h2::frame::PREFACE,
+        utils::{compare_no_case, same_authority, trim_ows},
     },
     storage::{
-        AsBuffer, Block, BodySize, Chunk, ChunkHeader, Flags, Kawa, Kind, Pair, ParsingPhase,
-        StatusLine, Store,
+        AsBuffer, Block, BodySize, Chunk, ChunkHeader, Flags, Kawa, Kind, Pair, ParsingErrorKind,
+        ParsingPhase, ParsingPhaseMarker, Role, StatusLine, Store, Version, EMPTY_CRUMB_NAME,
     },
 };
 
@@ -52,7 +58,43 @@ fn handle_recovery_error<T: AsBuffer>(
     }
 }
 
+/// Replace the obsolete line foldings in header values by a single space. The folded values are
+/// rewritten in place, as they are always shorter than the lines they span.
+#[cfg(feature = "tolerant-parsing")]
+fn unfold_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
+    let buf = kawa.storage.mut_buffer();
+    for block in &mut kawa.blocks {
+        if let Block::Header(Pair { val, .. }) = block {
+            let data = val.data(buf);
+            if !data.iter().any(|c| *c == b'\r' || *c == b'\n') {
+                continue;
+            }
+            let mut unfolded = Vec::with_capacity(data.len());
+            let mut i = 0;
+            while i < data.len() {
+                if data[i] == b'\r' || data[i] == b'\n' {
+                    // OWS CRLF RWS, or OWS LF RWS
+                    while unfolded.last().map_or(false, |c| is_space(*c)) {
+                        unfolded.pop();
+                    }
+                    i += if data[i] == b'\r' { 2 } else { 1 };
+                    while i < data.len() && is_space(data[i]) {
+                        i += 1;
+                    }
+                    unfolded.push(b' ');
+                } else {
+                    unfolded.push(data[i]);
+                    i += 1;
+                }
+            }
+            val.modify(buf, &unfolded);
+        }
+    }
+}
+
 fn process_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
+    #[cfg(feature = "tolerant-parsing")]
+    unfold_headers(kawa);
     let buf = kawa.storage.buffer();
 
     let (mut authority, path) = match &kawa.detached.status_line {
@@ -63,6 +105,13 @@ fn process_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
         } => {
             let uri = uri.data(buf);
             let method = method.data(buf);
+            let absolute_form =
+                !uri.is_empty() && uri[0] != b'/' && uri != b"*" && method != b"CONNECT";
+            if kawa.role == Role::Origin && absolute_form {
+                kawa.parsing_phase
+                    .error("Absolute-form request target on an origin server".into());
+                return;
+            }
             match parse_url(buf, method, uri) {
                 Some((authority, path)) => (authority, path),
                 _ => {
@@ -74,6 +123,20 @@ fn process_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
         _ => (Store::Empty, Store::Empty),
     };
 
+    let default_port: &[u8] = match &kawa.detached.status_line {
+        StatusLine::Request { uri, .. }
+            if uri.data(buf).len() >= 6 && compare_no_case(&uri.data(buf)[..6], b"https:") =>
+        {
+            b"443"
+        }
+        _ => b"80",
+    };
+    let mut connection_upgrade = false;
+    let mut connection_keep_alive = false;
+    let mut connection_close = false;
+    let mut upgrade = None;
+    // RFC 9112, 5.3: the Transfer-Encoding lines form a single list, only its last coding matters
+    let mut transfer_encoding: Option<Option<&[u8]>> = None;
     for block in &mut kawa.blocks {
         if let Block::Header(header) = block {
             let Store::Slice(key) = &header.key else {
@@ -84,6 +147,12 @@ fn process_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
                 // request line has higher priority than Host header
                 if let Store::Empty = authority {
                     mem::swap(&mut authority, &mut header.val);
+                } else if kawa.strict_host
+                    && !same_authority(authority.data(buf), header.val.data(buf), default_port)
+                {
+                    kawa.parsing_phase
+                        .error("Host header does not match the request target authority".into());
+                    return;
                 }
                 header.elide(); // Host header is elided
             } else if compare_no_case(key, b"content-length") {
@@ -95,13 +164,13 @@ fn process_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
                         return;
                     }
                 };
+                if transfer_encoding.is_some() {
+                    println!("WARNING: Found both a Transfer-Encoding and a Content-Length, ignoring the latter");
+                    header.elide();
+                    continue;
+                }
                 match kawa.body_size {
-                    BodySize::Empty => {}
-                    BodySize::Chunked => {
-                        println!("WARNING: Found both a Transfer-Encoding and a Content-Length, ignoring the latter");
-                        header.elide();
-                        continue;
-                    }
+                    BodySize::Empty | BodySize::Chunked => {}
                     BodySize::Length(previous_length) => {
                         if previous_length != length {
                             kawa.parsing_phase
@@ -114,33 +183,58 @@ fn process_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
                 }
                 kawa.body_size = BodySize::Length(length);
             } else if compare_no_case(key, b"transfer-encoding") {
-                let val = header.val.data(buf);
-                const CHUNKED: &[u8] = b"chunked";
-                if val.len() >= CHUNKED.len()
-                    && compare_no_case(&val[val.len() - CHUNKED.len()..], CHUNKED)
-                {
-                    match kawa.body_size {
-                        BodySize::Empty => {}
-                        BodySize::Chunked => {
-                            println!("WARNING: Found multiple Transfer-Encoding");
-                        }
-                        BodySize::Length(_) => {
-                            println!("WARNING: Found both a Content-Length and a Transfer-Encoding, ignoring the former");
-                        }
-                    }
-                    kawa.body_size = BodySize::Chunked;
+                let last_coding = header
+                    .val
+                    .data(buf)
+                    .split(|c| *c == b',')
+                    .map(trim_ows)
+                    .rfind(|coding| !coding.is_empty());
+                transfer_encoding = Some(last_coding.or(transfer_encoding.flatten()));
+            } else if compare_no_case(key, b"connection") {
+                for option in header.val.data(buf).split(|c| *c == b',').map(trim_ows) {
+                    connection_upgrade |= compare_no_case(option, b"upgrade");
+                    connection_keep_alive |= compare_no_case(option, b"keep-alive");
+                    connection_close |= compare_no_case(option, b"close");
                 }
+            } else if compare_no_case(key, b"upgrade") {
+                upgrade = Some(header.val.data(buf));
             }
         }
     }
+    if let Some(last_coding) = transfer_encoding {
+        if last_coding.map_or(false, |coding| compare_no_case(coding, b"chunked")) {
+            if let BodySize::Length(_) = kawa.body_size {
+                println!("WARNING: Found both a Content-Length and a Transfer-Encoding, ignoring the former");
+            }
+            kawa.body_size = BodySize::Chunked;
+        } else if kawa.kind == Kind::Request {
+            // RFC 7230, 3.3.3: the length of the request body can't be determined
+            kawa.parsing_phase
+                .error("Transfer-Encoding final coding is not chunked".into());
+            return;
+        } else {
+            // RFC 7230, 3.3.3: the response body is read until the connection closes
+            kawa.body_size = BodySize::Empty;
+        }
+    }
     match &mut kawa.detached.status_line {
         StatusLine::Request {
+            method,
             authority: old_authority,
             path: old_path,
             ..
         } => {
+            // RFC 9110, 9.3.6: a CONNECT request has no body, the connection becomes a tunnel
+            if method.equals(buf, b"CONNECT") {
+                kawa.tunnel = true;
+                kawa.body_size = BodySize::Length(0);
+            }
             *old_authority = authority;
             *old_path = path;
+            // RFC 7230, 3.3.3: a request without Content-Length nor Transfer-Encoding has no body
+            if kawa.body_size == BodySize::Empty {
+                kawa.body_size = BodySize::Length(0);
+            }
         }
         // RFC 2616, 10.2.5:
         // The 204 response MUST NOT include a message-body, and thus is always
@@ -154,31 +248,301 @@ fn process_headers<T: AsBuffer>(kawa: &mut Kawa<T>) {
         // terminated by an empty line.
         StatusLine::Response { code, .. }
             if *code == 204 || *code == 304 || (*code >= 100 && *code < 200) =>
+        {
+            if !matches!(kawa.body_size, BodySize::Empty | BodySize::Length(0)) {
+                println!(
+                    "WARNING: Found body framing in a {code} response, ignoring it: {:?}",
+                    kawa.body_size
+                );
+                kawa.ignored_framing = Some(kawa.body_size);
+            }
+            kawa.body_size = BodySize::Length(0);
+        }
+        // RFC 9112, 6.3: a 2xx response to a CONNECT request has no body, the connection
+        // becomes a tunnel
+        StatusLine::Response { code, .. } if kawa.connect_request && (200..300).contains(code) => {
+            kawa.tunnel = true;
+            kawa.body_size = BodySize::Length(0);
+        }
+        // RFC 9112, 6.3: the response to a HEAD request has no body, its framing headers
+        // describe the body the same GET request would have received
+        StatusLine::Response { .. } if kawa.head_request => {
+            kawa.body_size = BodySize::Length(0);
+        }
+        // without framing, an HTTP/1.0 response body is delimited by the connection close, an
+        // HTTP/1.1 response explicitly keeping the connection alive can't be: it has no body
+        StatusLine::Response { version, .. }
+            if matches!(version, Version::V11)
+                && kawa.body_size == BodySize::Empty
+                && connection_keep_alive
+                && !connection_close =>
         {
             kawa.body_size = BodySize::Length(0);
         }
         _ => {}
     };
+    // RFC 9110, 7.8: the Upgrade header is only meaningful with the "upgrade" Connection option,
+    // a request asks for the upgrade, a 101 response switches the protocol. The body framing
+    // no longer applies, the rest of the stream belongs to the new protocol.
+    let switching = match &kawa.detached.status_line {
+        StatusLine::Response { code, .. } => *code == 101,
+        _ => true,
+    };
+    if let (true, true, Some(protocols)) = (connection_upgrade, switching, upgrade) {
+        kawa.upgrade = Some(Store::from_slice(protocols));
+        kawa.body_size = BodySize::Empty;
+    }
 }
 
 pub trait ParserCallbacks<T: AsBuffer> {
+    /// Called once the status line is parsed, before any header
+    fn on_status_line(&mut self, _kawa: &mut Kawa<T>) {}
+    /// Called once all the headers are parsed and processed
     fn on_headers(&mut self, _kawa: &mut Kawa<T>) {}
+    /// Called with the body bytes as they are parsed, without the chunk headers
+    fn on_body(&mut self, _kawa: &Kawa<T>, _data: &[u8]) {}
+    /// Called once the trailer section is parsed, the trailers are the last Header blocks
+    fn on_trailers(&mut self, _kawa: &mut Kawa<T>) {}
+    /// Called once the message is entirely parsed, when the Terminated phase is reached
+    fn on_message_end(&mut self, _kawa: &mut Kawa<T>) {}
+    /// Called with a malformed header line, without its line ending, when it is skipped because
+    /// Kawa::skip_invalid_headers is set
+    fn on_invalid_header(&mut self, _kawa: &Kawa<T>, _line: &[u8]) {}
 }
 
 pub struct NoCallbacks;
 impl<T: AsBuffer> ParserCallbacks<T> for NoCallbacks {}
 
+/// Callbacks feeding the body bytes to a Hasher, the digest is available once the message is
+/// entirely parsed. The hasher is generic so any checksum or hash can be used.
+#[derive(Debug, Clone, Default)]
+pub struct HashingCallbacks<H: Hasher> {
+    pub hasher: H,
+    digest: Option<u64>,
+}
+
+impl<H: Hasher> HashingCallbacks<H> {
+    pub fn new(hasher: H) -> Self {
+        Self {
+            hasher,
+            digest: None,
+        }
+    }
+
+    /// Returns the digest of the body, or None if the message is not entirely parsed yet
+    pub fn digest(&self) -> Option<u64> {
+        self.digest
+    }
+}
+
+impl<T: AsBuffer, H: Hasher> ParserCallbacks<T> for HashingCallbacks<H> {
+    fn on_body(&mut self, _kawa: &Kawa<T>, data: &[u8]) {
+        self.hasher.write(data);
+    }
+    fn on_message_end(&mut self, _kawa: &mut Kawa<T>) {
+        self.digest = Some(self.hasher.finish());
+    }
+}
+
 pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks: &mut C) {
+    parse_until(kawa, callbacks, ParsingPhaseMarker::Terminated)
+}
+
+/// Parse until the parsing phase reaches `stop`, leaving the rest of the data unparsed. This lets
+/// the caller inspect the status line or the headers and abort early. Parsing can be resumed
+/// with parse or parse_until. If the message never enters the `stop` phase (e.g. Chunks for a
+/// message with a Content-Length) it is parsed as far as possible, like with parse.
+pub fn parse_until<T: AsBuffer, C: ParserCallbacks<T>>(
+    kawa: &mut Kawa<T>,
+    callbacks: &mut C,
+    stop: ParsingPhaseMarker,
+) {
+    let terminated = kawa.is_terminated();
+    parse_phases(kawa, callbacks, stop);
+    if !terminated && kawa.is_terminated() {
+        callbacks.on_message_end(kawa);
+    }
+}
+
+/// Signal that the peer closed the connection. A message whose body is delimited by the end of
+/// the connection (neither Content-Length nor chunked Transfer-Encoding) is then complete: the
+/// final Flags block is pushed and the Terminated phase is reached. The data received before must
+/// have been parsed. Returns false, leaving the Kawa untouched, for any other message, meaning it
+/// was truncated if it is not already terminated.
+pub fn end_of_input<T: AsBuffer, C: ParserCallbacks<T>>(
+    kawa: &mut Kawa<T>,
+    callbacks: &mut C,
+) -> bool {
+    if kawa.parsing_phase != ParsingPhase::Body || kawa.body_size != BodySize::Empty {
+        return false;
+    }
+    kawa.expects = 0;
+    kawa.parsing_phase = ParsingPhase::Terminated;
+    kawa.blocks.push_back(Block::Flags(Flags {
+        end_body: true,
+        end_chunk: false,
+        end_header: false,
+        end_stream: true,
+    }));
+    callbacks.on_message_end(kawa);
+    true
+}
+
+/// Returns the method of the request starting the buffer, before any Kawa is built (e.g. to
+/// dispatch a connection on the method). Only the bytes up to the first space are read.
+/// Returns None if the method is not entirely received yet or is not a valid token.
+pub fn peek_method(buf: &[u8]) -> Option<&[u8]> {
+    match tchar::take_while_fast(buf) {
+        Ok(([b' ', ..], method)) if !method.is_empty() => Some(method),
+        _ => None,
+    }
+}
+
+/// Parse a message whose kind is not known in advance. Before the status line is parsed, the kind
+/// is detected from the first bytes: a response starts with the HTTP version, a request with a
+/// method token. It overwrites the kind given to Kawa::new. Nothing is parsed until enough bytes
+/// are received to tell them apart, and the parsing fails if they match neither.
+pub fn parse_auto<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks: &mut C) {
+    if kawa.parsing_phase == ParsingPhase::StatusLine {
+        let unparsed_buf = kawa.storage.unparsed_data();
+        let len = min(unparsed_buf.len(), 5);
+        if unparsed_buf.is_empty() {
+            return;
+        } else if unparsed_buf.starts_with(b"HTTP/") {
+            kawa.kind = Kind::Response;
+        } else {
+            match tchar::take_while_fast(unparsed_buf) {
+                Ok(([b' ', ..], method)) if !method.is_empty() => kawa.kind = Kind::Request,
+                // either an incomplete version or an incomplete method
+                _ if unparsed_buf[..len] == b"HTTP/"[..len] => return,
+                Err(NomErr::Incomplete(_)) => return,
+                _ => {
+                    kawa.parsing_phase
+                        .error("Neither a request line nor a status line".into());
+                    return;
+                }
+            }
+        }
+    }
+    parse(kawa, callbacks)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of bytes scanned by line_end and by the line parsers
+    static SCANNED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Returns the length of the line starting the buffer, LF included, the first `scanned` bytes are
+/// known not to contain it
+#[inline]
+fn line_end(buf: &[u8], scanned: usize) -> Option<usize> {
+    let scanned = min(scanned, buf.len());
+    let end = buf[scanned..]
+        .iter()
+        .position(|c| *c == b'\n')
+        .map(|position| scanned + position + 1);
+    #[cfg(test)]
+    SCANNED.with(|counter| {
+        let line = end.unwrap_or(0);
+        counter.set(counter.get() + end.unwrap_or(buf.len()) - scanned + line)
+    });
+    end
+}
+
+/// Length of the request target in a request line, possibly incomplete
+fn target_length(line: &[u8]) -> usize {
+    let target = match line.iter().position(|c| *c == b' ') {
+        Some(space) => &line[space + 1..],
+        None => return 0,
+    };
+    target
+        .iter()
+        .position(|c| *c == b' ' || *c == b'\r' || *c == b'\n')
+        .unwrap_or(target.len())
+}
+
+fn parse_phases<T: AsBuffer, C: ParserCallbacks<T>>(
+    kawa: &mut Kawa<T>,
+    callbacks: &mut C,
+    stop: ParsingPhaseMarker,
+) {
     let mut need_processing = false;
+    let mut trailers_parsed = false;
+    let mut status_line_parsed = false;
     loop {
         let buf = kawa.storage.buffer();
         let mut unparsed_buf = kawa.storage.unparsed_data();
         while !unparsed_buf.is_empty() {
+            if kawa.parsing_phase.marker() == stop {
+                break;
+            }
+            if matches!(
+                kawa.parsing_phase,
+                ParsingPhase::StatusLine | ParsingPhase::Headers | ParsingPhase::Trailers
+            ) {
+                // the line parsers are only called once the end of the line is received, so a
+                // line received in several reads is scanned once instead of once per read
+                let end = line_end(unparsed_buf, kawa.line_scanned);
+                if kawa.parsing_phase != ParsingPhase::Trailers {
+                    // an incomplete line is counted whole, the limits are crossed before its end
+                    let line = &unparsed_buf[..end.unwrap_or(unparsed_buf.len())];
+                    let start = match (kawa.parsing_phase, kawa.header_region) {
+                        (ParsingPhase::Headers, Some((start, _))) => start,
+                        _ => buf.offset(unparsed_buf),
+                    };
+                    if buf.offset(unparsed_buf) + line.len() - start > kawa.max_header_section {
+                        kawa.parsing_phase
+                            .error(ParsingErrorKind::HeaderSectionTooLarge);
+                        break;
+                    }
+                    if kawa.parsing_phase == ParsingPhase::StatusLine
+                        && kawa.kind == Kind::Request
+                        && target_length(line) > kawa.max_uri_length
+                    {
+                        kawa.parsing_phase.error(ParsingErrorKind::UriTooLong);
+                        break;
+                    }
+                }
+                match end {
+                    Some(_) => kawa.line_scanned = 0,
+                    None => {
+                        kawa.line_scanned = unparsed_buf.len();
+                        if kawa.parsing_phase != ParsingPhase::StatusLine
+                            && unparsed_buf.len() > kawa.max_header_line.saturating_add(2)
+                        {
+                            kawa.parsing_phase
+                                .error(ParsingErrorKind::HeaderLineTooLong);
+                        }
+                        break;
+                    }
+                }
+            }
             match kawa.parsing_phase {
                 ParsingPhase::StatusLine => {
+                    if kawa.kind == Kind::Request && unparsed_buf[0] == b'P' {
+                        if unparsed_buf.starts_with(PREFACE) {
+                            // the preface is left unparsed for an H2 parser to take over
+                            kawa.detached.status_line = StatusLine::Request {
+                                version: Version::V20,
+                                method: Store::Static(b"PRI"),
+                                uri: Store::Static(b"*"),
+                                authority: Store::Empty,
+                                path: Store::Empty,
+                            };
+                            kawa.parsing_phase.error(ParsingErrorKind::H2Preface);
+                            break;
+                        }
+                        if PREFACE.starts_with(unparsed_buf) {
+                            // can't tell an incomplete preface from a PRI request yet
+                            break;
+                        }
+                    }
                     match kawa.kind {
                         Kind::Request => match parse_request_line(unparsed_buf) {
                             Ok((i, (method, uri, version))) => {
+                                let start = buf.offset(unparsed_buf);
+                                kawa.header_region = Some((start, start));
                                 kawa.detached.status_line = StatusLine::Request {
                                     version,
                                     method: Store::new_slice(buf, method),
@@ -195,6 +559,8 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                         },
                         Kind::Response => match parse_response_line(unparsed_buf) {
                             Ok((i, (version, status, code, reason))) => {
+                                let start = buf.offset(unparsed_buf);
+                                kawa.header_region = Some((start, start));
                                 kawa.detached.status_line = StatusLine::Response {
                                     version,
                                     code,
@@ -211,49 +577,149 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                     };
                     kawa.blocks.push_back(Block::StatusLine);
                     kawa.parsing_phase = ParsingPhase::Headers;
+                    status_line_parsed = true;
+                    break;
                 }
-                ParsingPhase::Headers => match parse_header_or_cookie(unparsed_buf) {
-                    Ok((i, Some((key, val)))) => {
-                        kawa.blocks.push_back(Block::Header(Pair {
-                            key: Store::new_slice(buf, key),
-                            val: Store::new_slice(buf, val),
-                        }));
-                        unparsed_buf = i;
-                    }
-                    Ok((i, None)) => {
-                        kawa.blocks.push_back(Block::Cookies);
-                        kawa.parsing_phase = ParsingPhase::Cookies { first: true };
-                        unparsed_buf = i;
-                    }
-                    Err(NomErr::Incomplete(_)) => {
-                        break;
+                ParsingPhase::Headers => {
+                    // CRLF included
+                    let max_line = kawa.max_header_line.saturating_add(2);
+                    #[cfg(feature = "tolerant-parsing")]
+                    if is_space(unparsed_buf[0]) {
+                        let last = kawa.blocks.len().checked_sub(1);
+                        if let Some(Block::Header(header)) = last.map(|last| &mut kawa.blocks[last])
+                        {
+                            match parse_obs_fold(unparsed_buf) {
+                                Ok((i, _)) if unparsed_buf.len() - i.len() > max_line => {
+                                    kawa.parsing_phase
+                                        .error(ParsingErrorKind::HeaderLineTooLong);
+                                    break;
+                                }
+                                Err(NomErr::Incomplete(_)) if unparsed_buf.len() > max_line => {
+                                    kawa.parsing_phase
+                                        .error(ParsingErrorKind::HeaderLineTooLong);
+                                    break;
+                                }
+                                Ok((i, val)) => {
+                                    // the value spans the folded lines until process_headers
+                                    let start = buf.offset(header.val.data(buf));
+                                    let end = buf.offset(val) + val.len();
+                                    header.val = Store::new_slice(buf, &buf[start..end]);
+                                    unparsed_buf = i;
+                                    continue;
+                                }
+                                Err(error) => {
+                                    kawa.parsing_phase = handle_error(kawa, error);
+                                    break;
+                                }
+                            }
+                        }
                     }
-                    Err(NomErr::Error(error)) | Err(NomErr::Failure(error)) => {
-                        match crlf(unparsed_buf) {
-                            Ok((i, _)) => {
-                                need_processing = true;
-                                unparsed_buf = i;
-                                break;
+                    match parse_header_or_cookie(unparsed_buf) {
+                        Ok(_) if kawa.header_count >= kawa.max_headers => {
+                            kawa.parsing_phase.error(ParsingErrorKind::TooManyHeaders);
+                            break;
+                        }
+                        Ok((i, Some(_))) if unparsed_buf.len() - i.len() > max_line => {
+                            kawa.parsing_phase
+                                .error(ParsingErrorKind::HeaderLineTooLong);
+                            break;
+                        }
+                        Ok((i, Some((key, val)))) => {
+                            kawa.header_count += 1;
+                            kawa.blocks.push_back(Block::Header(Pair {
+                                key: Store::new_slice(buf, key),
+                                val: Store::new_slice(buf, val),
+                            }));
+                            unparsed_buf = i;
+                        }
+                        Ok((i, None)) => {
+                            kawa.header_count += 1;
+                            kawa.line_length = unparsed_buf.len() - i.len();
+                            kawa.blocks.push_back(Block::Cookies);
+                            kawa.parsing_phase = ParsingPhase::Cookies { first: true };
+                            unparsed_buf = i;
+                        }
+                        Err(NomErr::Incomplete(_)) => {
+                            if unparsed_buf.len() > max_line {
+                                kawa.parsing_phase
+                                    .error(ParsingErrorKind::HeaderLineTooLong);
                             }
-                            Err(recovery_error) => {
-                                kawa.parsing_phase =
-                                    handle_recovery_error(kawa, error, recovery_error);
-                                break;
+                            break;
+                        }
+                        Err(NomErr::Error(_)) | Err(NomErr::Failure(_))
+                            if is_space_before_colon(unparsed_buf) =>
+                        {
+                            kawa.parsing_phase
+                                .error("Whitespace between header field name and colon".into());
+                            break;
+                        }
+                        Err(NomErr::Error(error)) | Err(NomErr::Failure(error)) => {
+                            match crlf(unparsed_buf) {
+                                Ok((i, _)) => {
+                                    need_processing = true;
+                                    unparsed_buf = i;
+                                    break;
+                                }
+                                Err(_) if kawa.skip_invalid_headers => {
+                                    if kawa.header_count >= kawa.max_headers {
+                                        kawa.parsing_phase.error(ParsingErrorKind::TooManyHeaders);
+                                        break;
+                                    }
+                                    kawa.header_count += 1;
+                                    // the line end was found before calling the line parsers
+                                    let Some(lf) = unparsed_buf.iter().position(|c| *c == b'\n')
+                                    else {
+                                        break;
+                                    };
+                                    let line = &unparsed_buf[..lf];
+                                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                                    callbacks.on_invalid_header(kawa, line);
+                                    unparsed_buf = &unparsed_buf[lf + 1..];
+                                }
+                                Err(recovery_error) => {
+                                    kawa.parsing_phase =
+                                        handle_recovery_error(kawa, error, recovery_error);
+                                    break;
+                                }
                             }
                         }
                     }
-                },
+                }
                 ParsingPhase::Cookies { ref mut first } => {
                     match parse_single_crumb(unparsed_buf, *first) {
+                        Ok(_) if kawa.header_count >= kawa.max_headers => {
+                            kawa.parsing_phase.error(ParsingErrorKind::TooManyHeaders);
+                            break;
+                        }
+                        Ok((i, _))
+                            if kawa.line_length + unparsed_buf.len() - i.len()
+                                > kawa.max_header_line =>
+                        {
+                            kawa.parsing_phase
+                                .error(ParsingErrorKind::HeaderLineTooLong);
+                            break;
+                        }
                         Ok((i, (key, val))) => {
                             *first = false;
+                            kawa.header_count += 1;
+                            kawa.line_length += unparsed_buf.len() - i.len();
+                            // the value of a crumb without "=" starts where its empty key does
+                            let key = if key.is_empty() && buf.offset(val) > buf.offset(key) {
+                                Store::Static(EMPTY_CRUMB_NAME)
+                            } else {
+                                Store::new_slice(buf, key)
+                            };
                             kawa.detached.jar.push_back(Pair {
-                                key: Store::new_slice(buf, key),
+                                key,
                                 val: Store::new_slice(buf, val),
                             });
                             unparsed_buf = i;
                         }
                         Err(NomErr::Incomplete(_)) => {
+                            if kawa.line_length + unparsed_buf.len() > kawa.max_header_line {
+                                kawa.parsing_phase
+                                    .error(ParsingErrorKind::HeaderLineTooLong);
+                            }
                             break;
                         }
                         Err(NomErr::Error(error)) | Err(NomErr::Failure(error)) => {
@@ -280,6 +746,7 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                         kawa.expects -= taken;
                         taken
                     };
+                    callbacks.on_body(kawa, &unparsed_buf[..taken]);
                     kawa.blocks.push_back(Block::Chunk(Chunk {
                         data: Store::new_slice(buf, &unparsed_buf[..taken]),
                     }));
@@ -296,19 +763,27 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                 }
                 ParsingPhase::Chunks { ref mut first } => {
                     if kawa.expects == 0 {
-                        let (i, (size_hexa, size)) = match parse_chunk_header(*first, unparsed_buf)
-                        {
-                            Ok(ok) => {
-                                *first = false;
-                                ok
-                            }
-                            Err(error) => {
-                                kawa.parsing_phase = handle_error(kawa, error);
-                                break;
-                            }
-                        };
+                        let (i, ((size_hexa, size), extensions)) =
+                            match parse_chunk_header(*first, unparsed_buf) {
+                                Ok(ok) => {
+                                    *first = false;
+                                    ok
+                                }
+                                Err(error) => {
+                                    kawa.parsing_phase = handle_error(kawa, error);
+                                    break;
+                                }
+                            };
                         kawa.expects = size;
                         if size == 0 {
+                            if !extensions.is_empty() {
+                                // the extensions of the last chunk are kept, it is followed by
+                                // the Flags ending the body
+                                kawa.blocks.push_back(Block::ChunkHeader(ChunkHeader {
+                                    length: Store::new_slice(buf, size_hexa),
+                                    extensions: Store::new_slice(buf, extensions),
+                                }));
+                            }
                             kawa.blocks.push_back(Block::Flags(Flags {
                                 end_body: true,
                                 end_chunk: false,
@@ -319,6 +794,11 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                         } else {
                             kawa.blocks.push_back(Block::ChunkHeader(ChunkHeader {
                                 length: Store::new_slice(buf, size_hexa),
+                                extensions: if extensions.is_empty() {
+                                    Store::Empty
+                                } else {
+                                    Store::new_slice(buf, extensions)
+                                },
                             }));
                         }
                         unparsed_buf = i;
@@ -326,6 +806,7 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                         let len = unparsed_buf.len();
                         let taken = min(len, kawa.expects);
                         kawa.expects -= taken;
+                        callbacks.on_body(kawa, &unparsed_buf[..taken]);
                         kawa.blocks.push_back(Block::Chunk(Chunk {
                             data: Store::new_slice(buf, &unparsed_buf[..taken]),
                         }));
@@ -341,7 +822,20 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                     }
                 }
                 ParsingPhase::Trailers => match parse_header(unparsed_buf) {
+                    Ok(_) if kawa.header_count >= kawa.max_headers => {
+                        kawa.parsing_phase.error(ParsingErrorKind::TooManyHeaders);
+                        break;
+                    }
+                    Ok((i, _))
+                        if unparsed_buf.len() - i.len()
+                            > kawa.max_header_line.saturating_add(2) =>
+                    {
+                        kawa.parsing_phase
+                            .error(ParsingErrorKind::HeaderLineTooLong);
+                        break;
+                    }
                     Ok((i, (key, val))) => {
+                        kawa.header_count += 1;
                         kawa.blocks.push_back(Block::Header(Pair {
                             key: Store::new_slice(buf, key),
                             val: Store::new_slice(buf, val),
@@ -349,6 +843,17 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                         unparsed_buf = i;
                     }
                     Err(NomErr::Incomplete(_)) => {
+                        if unparsed_buf.len() > kawa.max_header_line.saturating_add(2) {
+                            kawa.parsing_phase
+                                .error(ParsingErrorKind::HeaderLineTooLong);
+                        }
+                        break;
+                    }
+                    Err(NomErr::Error(_)) | Err(NomErr::Failure(_))
+                        if is_space_before_colon(unparsed_buf) =>
+                    {
+                        kawa.parsing_phase
+                            .error("Whitespace between header field name and colon".into());
                         break;
                     }
                     Err(NomErr::Error(error)) | Err(NomErr::Failure(error)) => {
@@ -361,6 +866,7 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                                     end_header: true,
                                     end_stream: true,
                                 }));
+                                trailers_parsed = true;
                                 unparsed_buf = i;
                                 break;
                             }
@@ -378,12 +884,18 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
         // it is absolutely essential that this line is called at the end of a parsing phase
         // do not for any reason short circuit this line
         kawa.storage.head = buf.offset(unparsed_buf);
-        if need_processing {
+        if status_line_parsed {
+            status_line_parsed = false;
+            callbacks.on_status_line(kawa);
+        } else if need_processing {
             process_headers(kawa);
             if kawa.is_error() {
                 return;
             }
             need_processing = false;
+            if let Some((_, end)) = &mut kawa.header_region {
+                *end = kawa.storage.head;
+            }
             kawa.parsing_phase = match kawa.body_size {
                 BodySize::Chunked => ParsingPhase::Chunks { first: true },
                 BodySize::Length(0) => ParsingPhase::Terminated,
@@ -404,7 +916,35 @@ pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(kawa: &mut Kawa<T>, callbacks:
                 end_stream: kawa.is_terminated(),
             }));
         } else {
+            if trailers_parsed {
+                callbacks.on_trailers(kawa);
+            }
             return;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Buffer;
+    use std::io::Write;
+
+    #[test]
+    fn test_linear_scan() {
+        let mut message = b"GET / HTTP/1.1\r\nHost: example.com\r\nX-Long: ".to_vec();
+        message.extend(std::iter::repeat(b'a').take(4000));
+        message.extend(b"\r\nContent-Length: 0\r\n\r\n");
+
+        let mut kawa = Kawa::new(Kind::Request, Buffer::new(vec![0; 8192]));
+        SCANNED.with(|counter| counter.set(0));
+        for byte in &message {
+            kawa.storage.write_all(&[*byte]).expect("WRITE");
+            parse(&mut kawa, &mut NoCallbacks);
+        }
+        assert!(kawa.is_terminated());
+        // every byte is scanned by line_end and by a line parser
+        let scanned = SCANNED.with(|counter| counter.get());
+        assert!(scanned <= 2 * message.len(), "{scanned}");
+    }
+}
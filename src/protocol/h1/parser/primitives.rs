@@ -1,7 +1,7 @@
 use nom::{
     bytes::{
         complete::{tag as tag_complete, take_while as take_while_complete},
-        streaming::{tag, take, take_while},
+        streaming::{tag, take, take_while, take_while1},
     },
     character::{
         complete::char as char_complete,
@@ -42,6 +42,23 @@ impl std::ops::Deref for CharTable {
     }
 }
 
+impl CharTable {
+    /// Bitmaps of the valid characters indexed by low nibble, for the high nibbles 0-7 and 8-F:
+    /// bit h of bitmaps[0][l] is set if the character 0xhl is valid, bit h of bitmaps[1][l] if
+    /// the character 0x(h+8)l is valid.
+    pub const fn nibble_bitmaps(&self) -> [[u8; 16]; 2] {
+        let mut bitmaps = [[0; 16]; 2];
+        let mut c = 0;
+        while c < 256 {
+            if self.0[c] {
+                bitmaps[c >> 7][c & 0xF] |= 1 << ((c >> 4) & 7);
+            }
+            c += 1;
+        }
+        bitmaps
+    }
+}
+
 /// Character invalid ranges, defines up to 8 ranges of invalid characters
 #[repr(align(16))]
 pub struct CharRanges([u8; 16]);
@@ -172,8 +189,14 @@ fn space(i: &[u8]) -> IResult<&[u8], char> {
     char(' ')(i)
 }
 
+/// Line terminator, the tolerant parser also accepts a bare LF (RFC 9112, 2.2), consistently
+/// for the start line, the headers, the chunk framing and the trailers.
 #[inline]
 pub fn crlf(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    #[cfg(feature = "tolerant-parsing")]
+    if i.first() == Some(&b'\n') {
+        return Ok((&i[1..], &i[..1]));
+    }
     tag(b"\r\n")(i)
 }
 
@@ -266,6 +289,31 @@ pub fn parse_header(i: &[u8]) -> IResult<&[u8], (&[u8], &[u8])> {
     Ok((i, (key, val)))
 }
 
+/// detect whitespace between a header field name and the colon, such a header must be rejected
+/// as it can't be reliably interpreted (RFC 7230, 3.2.4)
+///
+/// example: `Host : example.com\r\n`
+#[inline]
+pub fn is_space_before_colon(i: &[u8]) -> bool {
+    let result: IResult<&[u8], &[u8]> = tchar::take_while_fast(i)
+        .and_then(|(i, _)| take_while1(is_space)(i))
+        .and_then(|(i, _)| tag(b":")(i));
+    result.is_ok()
+}
+
+/// parse an obsolete line folding (RFC 7230, 3.2.4), a line starting with spaces or tabs that
+/// continues the value of the previous header, including terminating CRLF
+///
+/// example: ` continued value\r\n`
+#[cfg(feature = "tolerant-parsing")]
+#[inline]
+pub fn parse_obs_fold(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (i, _) = take_while(is_space)(i)?;
+    let (i, val) = achar::take_while_fast(i)?;
+    let (i, _) = crlf(i)?;
+    Ok((i, val))
+}
+
 /// parse a single crumb from a Cookie header
 ///
 /// examples:
@@ -420,18 +468,32 @@ pub fn chunk_size(i: &[u8]) -> IResult<&[u8], (&[u8], usize)> {
     }
 }
 
+/// parse the chunk extensions following a chunk size, without the leading semicolon
+/// (RFC 7230, 4.1.1), returns an empty slice if there are none
+///
+/// example: `;name=value;other`
 #[inline]
-pub fn parse_chunk_header(first: bool, i: &[u8]) -> IResult<&[u8], (&[u8], usize)> {
-    if first {
-        let (i, size) = chunk_size(i)?;
-        let (i, _) = crlf(i)?;
-        Ok((i, size))
-    } else {
-        let (i, _) = crlf(i)?;
-        let (i, size) = chunk_size(i)?;
-        let (i, _) = crlf(i)?;
-        Ok((i, size))
+pub fn chunk_extensions(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (i, _) = take_while(is_space)(i)?;
+    let (i, semicolon) = opt(char(';'))(i)?;
+    if semicolon.is_none() {
+        return Ok((i, &i[..0]));
     }
+    achar::take_while_fast(i)
+}
+
+/// parse a chunk header, including terminating CRLF and the CRLF ending the previous chunk if
+/// it is not the first one
+///
+/// example: `5;name=value\r\n`
+#[inline]
+#[allow(clippy::type_complexity)]
+pub fn parse_chunk_header(first: bool, i: &[u8]) -> IResult<&[u8], ((&[u8], usize), &[u8])> {
+    let i = if first { i } else { crlf(i)?.0 };
+    let (i, size) = chunk_size(i)?;
+    let (i, extensions) = chunk_extensions(i)?;
+    let (i, _) = crlf(i)?;
+    Ok((i, (size, extensions)))
 }
 
 #[inline]
@@ -502,9 +564,10 @@ pub fn parse_url(buffer: &[u8], method: &[u8], i: &[u8]) -> Option<(Store, Store
     if i.is_empty() {
         return Some((Store::Empty, Store::Static(b"/")));
     }
-    let url = if compare_no_case(method, b"OPTIONS") {
+    // methods are case sensitive (RFC 9110, 9.1)
+    let url = if method == b"OPTIONS" {
         parse_asterisk_form(buffer, i)
-    } else if compare_no_case(method, b"CONNECT") {
+    } else if method == b"CONNECT" {
         parse_authority_form(buffer, i)
     } else if i[0] == b'/' {
         parse_origin_form(buffer, i)
@@ -539,6 +602,147 @@ mod tests {
         );
     }
 
+    type TakeWhile = fn(&[u8]) -> IResult<&[u8], &[u8]>;
+
+    /// Check that the ranges used by the simd path and the table used by the scalar path of a
+    /// lookup agree on every byte.
+    fn check_lookup(
+        name: &str,
+        ranges: &CharRanges,
+        length: i32,
+        table: &CharTable,
+        take_while_fast: TakeWhile,
+    ) {
+        for c in 0..=255u8 {
+            let in_ranges = ranges[..length as usize]
+                .chunks(2)
+                .any(|range| range[0] <= c && c <= range[1]);
+            assert_eq!(table[c as usize], !in_ranges, "{name}: {c:#x}");
+            // long enough to go through the simd loop, terminated by a byte invalid everywhere
+            let mut input = [c; 33];
+            input[32] = 0;
+            let (_, taken) = take_while_fast(&input).expect("TAKE WHILE");
+            assert_eq!(
+                taken.len(),
+                if in_ranges { 0 } else { 32 },
+                "{name}: {c:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_lookups_consistency() {
+        macro_rules! check {
+            ($($name:ident),*) => {$(
+                check_lookup(
+                    stringify!($name),
+                    &$name::RANGES,
+                    $name::LENGTH,
+                    &$name::TABLE,
+                    $name::take_while_fast,
+                );
+            )*};
+        }
+        check!(tchar, vchar, ck_char, cv_char, achar);
+        // ISO-8859-1 characters are only accepted by the tolerant parser
+        assert_eq!(achar::predicate(0xE9), cfg!(feature = "tolerant-parsing"));
+        assert!(!achar::predicate(0x7F));
+        assert!(!achar::predicate(0x80));
+    }
+
+    #[test]
+    fn test_simd_positions() {
+        // an invalid byte at every position of the simd blocks and of the remaining bytes
+        for length in [15, 16, 31, 32, 33, 64, 95] {
+            for position in 0..length {
+                let mut input = vec![b'a'; length];
+                input[position] = b'\r';
+                let (_, fast) = vchar::take_while_complete_fast(&input).expect("FAST");
+                let (_, slow) = vchar::take_while_complete(&input).expect("SLOW");
+                assert_eq!(fast.len(), position);
+                assert_eq!(fast, slow);
+                input[position] = 0xE9;
+                let (_, taken) = achar::take_while_complete_fast(&input).expect("FAST");
+                let expected = if achar::predicate(0xE9) {
+                    length
+                } else {
+                    position
+                };
+                assert_eq!(taken.len(), expected, "{length} {position}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_simd_random() {
+        // the simd path (sse4.2/avx2 or neon) against the scalar path, on random inputs mixing
+        // valid and invalid bytes
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        macro_rules! check {
+            ($input:expr, $($name:ident),*) => {$(
+                let (_, fast) = $name::take_while_complete_fast($input).expect("FAST");
+                let (_, slow) = $name::take_while_complete($input).expect("SLOW");
+                assert_eq!(fast, slow, "{}: {:?}", stringify!($name), $input);
+                match ($name::take_while_fast($input), $name::take_while($input)) {
+                    (Ok(fast), Ok(slow)) => assert_eq!(fast, slow),
+                    (Err(nom::Err::Incomplete(_)), Err(nom::Err::Incomplete(_))) => {}
+                    (fast, slow) => panic!("{}: {fast:?} {slow:?}", stringify!($name)),
+                }
+            )*};
+        }
+        for _ in 0..2000 {
+            let length = (random() % 100) as usize;
+            // mostly valid bytes, so the invalid one can be anywhere in the input
+            let input = (0..length)
+                .map(|_| {
+                    let byte = random();
+                    if byte % 16 == 0 {
+                        (byte >> 8) as u8
+                    } else {
+                        b'a' + (byte >> 8) as u8 % 26
+                    }
+                })
+                .collect::<Vec<_>>();
+            check!(&input, tchar, vchar, ck_char, cv_char, achar);
+        }
+    }
+
+    #[test]
+    fn test_compare_no_case() {
+        // the letters and the bytes distant by 0x20 from them
+        let boundaries = [
+            b'@', b'A', b'Z', b'[', b'`', b'a', b'z', b'{', b'_', 0x7F, 0xC1, 0xE1, 0x80, 0xFF,
+        ];
+        for length in [1, 15, 16, 17, 31, 32, 33, 48] {
+            let left = (0..length)
+                .map(|i| b'a' + (i % 26) as u8)
+                .collect::<Vec<_>>();
+            assert!(compare_no_case(&left, &left.to_ascii_uppercase()));
+            assert!(!compare_no_case(&left, &left[1..]));
+            for position in [0, length / 2, length - 1] {
+                for a in boundaries {
+                    for b in boundaries {
+                        let mut left = left.clone();
+                        let mut right = left.to_ascii_uppercase();
+                        left[position] = a;
+                        right[position] = b;
+                        assert_eq!(
+                            compare_no_case(&left, &right),
+                            a.eq_ignore_ascii_case(&b),
+                            "{length} {position} {a:#x} {b:#x}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_asterisk_form() {
         // server-wide:
@@ -575,6 +779,24 @@ mod tests {
         test_url("OPTIONS", "/index.html?k=v#h", ("", "/index.html?k=v#h"));
     }
 
+    #[test]
+    fn test_custom_methods() {
+        test_url("PATCH", "/x", ("", "/x"));
+        test_url("PRI", "/", ("", "/"));
+        test_url("PRI", "http://www.example.org/x", ("www.example.org", "/x"));
+        test_url(
+            "VERSION-CONTROL-WITH-A-VERY-LONG-CUSTOM-METHOD",
+            "/index.html?k=v",
+            ("", "/index.html?k=v"),
+        );
+        // methods are case sensitive, these are not OPTIONS and CONNECT
+        test_url("options", "/x", ("", "/x"));
+        test_url("connect", "/x", ("", "/x"));
+        // the asterisk and authority forms are reserved to OPTIONS and CONNECT
+        assert!(parse_url(b"*", b"PRI", b"*").is_none());
+        assert!(parse_url(b"www.example.org:443", b"PATCH", b"www.example.org:443").is_none());
+    }
+
     #[test]
     fn test_absolute_form() {
         // empty path
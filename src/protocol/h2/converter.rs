@@ -1,15 +1,318 @@
+use std::mem;
+
 use crate::{
-    protocol::utils::compare_no_case,
-    storage::{
-        AsBuffer, Block, BlockConverter, Chunk, Flags, Kawa, OutBlock, Pair, StatusLine, Store,
+    protocol::{
+        h2::{
+            frame::{
+                FrameHeader, FrameType, DEFAULT_MAX_FRAME_SIZE, END_HEADERS, END_STREAM,
+                FRAME_HEADER_SIZE,
+            },
+            hpack::{table::ENTRY_OVERHEAD, Encoder},
+        },
+        utils::compare_no_case,
     },
+    storage::{AsBuffer, Block, BlockConverter, Chunk, Flags, Kawa, Kind, Pair, StatusLine, Store},
 };
 
-pub struct H2BlockConverter;
+/// Connection-specific header fields can't be sent in HTTP/2 (RFC 9113, 8.2.2), the Host header
+/// is replaced by the :authority pseudo header
+fn is_connection_specific(key: &[u8], val: &[u8]) -> bool {
+    compare_no_case(key, b"connection")
+        || compare_no_case(key, b"host")
+        || compare_no_case(key, b"http2-settings")
+        || compare_no_case(key, b"keep-alive")
+        || compare_no_case(key, b"proxy-connection")
+        || compare_no_case(key, b"te") && !compare_no_case(val, b"trailers")
+        || compare_no_case(key, b"trailer")
+        || compare_no_case(key, b"transfer-encoding")
+        || compare_no_case(key, b"upgrade")
+}
+
+/// Size of a header field in a header list (RFC 9113, 6.5.2)
+fn field_size(name_len: usize, value_len: usize) -> usize {
+    name_len + value_len + ENTRY_OVERHEAD
+}
+
+/// H2 converter, each frame starts with its 9 bytes header, and each delimiter pushed in the
+/// out vector marks the end of a frame. All the frames belong to the stream stream_id.
+///
+/// Header fields are HPACK encoded and accumulated until the end of the header section, the
+/// resulting header block is pushed as a single Store::Alloc. A header block larger than
+/// max_frame_size is split in a HEADERS frame followed by CONTINUATION frames, only the last one
+/// carries END_HEADERS. DATA payloads are not copied, a Chunk larger than max_frame_size is
+/// split in several frames. Headers received after the body are trailers, they are sent in a
+/// last HEADERS frame carrying END_STREAM. Otherwise the last DATA frame carries END_STREAM, or
+/// an empty DATA frame if the end of the stream was not parsed along the last Chunk.
+///
+/// A prepare call can be limited to a number of frames with max_frames (0 meaning unlimited).
+/// Once the budget is exhausted, the current block is put back in front of the blocks and the
+/// conversion stops, it resumes on the next prepare call.
+///
+/// DATA payloads are also limited by the flow-control window, which is unlimited by default.
+/// A Chunk larger than the window is split, the part that doesn't fit is put back in front of
+/// the blocks and the conversion stops until more window is granted.
+///
+/// A header section larger than max_header_list_size stops the conversion with an error. The
+/// whole section is measured before encoding it, if it is available, so the HPACK state is left
+/// untouched. Otherwise the fields already encoded are in the dynamic table and the connection
+/// should be closed.
+#[derive(Debug, Clone)]
+pub struct H2BlockConverter {
+    stream_id: u32,
+    pub max_frames: usize,
+    /// Maximum size of a frame payload, as advertised by the peer with SETTINGS_MAX_FRAME_SIZE
+    pub max_frame_size: usize,
+    /// Number of DATA payload bytes that can still be sent, as allowed by the peer flow-control
+    /// windows (the smallest of the connection and stream windows)
+    pub window: usize,
+    /// Maximum size of a header section, as advertised by the peer with
+    /// SETTINGS_MAX_HEADER_LIST_SIZE (unlimited by default)
+    pub max_header_list_size: usize,
+    pub encoder: Encoder,
+    frames: usize,
+    header_block: Vec<u8>,
+    /// Size of the header section being encoded, as defined in RFC 9113 (section 6.5.2)
+    header_list_size: usize,
+    /// END_STREAM was set on the last DATA frame, before the Flags ending the stream
+    stream_ended: bool,
+}
+
+impl Default for H2BlockConverter {
+    fn default() -> Self {
+        Self {
+            stream_id: 1,
+            max_frames: 0,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            window: usize::MAX,
+            max_header_list_size: usize::MAX,
+            encoder: Encoder::default(),
+            frames: 0,
+            header_block: Vec::new(),
+            header_list_size: 0,
+            stream_ended: false,
+        }
+    }
+}
+
+impl H2BlockConverter {
+    /// Create a converter for the stream stream_id
+    pub fn new(stream_id: u32) -> Self {
+        Self {
+            stream_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn stream_id(&self) -> u32 {
+        self.stream_id
+    }
+
+    /// Change the stream of the next frames, this allows to reuse a converter (and its HPACK
+    /// state) for all the streams of a connection.
+    pub fn set_stream_id(&mut self, stream_id: u32) {
+        self.stream_id = stream_id;
+    }
+
+    /// Streams initiated by a client (requests and their responses) have odd ids, streams
+    /// initiated by a server (pushed responses) have even ids. The stream 0 is reserved for
+    /// the connection.
+    fn valid_stream_id(&self, kind: Kind) -> bool {
+        match kind {
+            _ if self.stream_id == 0 || self.stream_id > 0x7FFF_FFFF => false,
+            Kind::Request => self.stream_id % 2 == 1,
+            Kind::Response => true,
+        }
+    }
+
+    pub fn with_max_frames(max_frames: usize) -> Self {
+        Self {
+            max_frames,
+            ..Default::default()
+        }
+    }
+
+    /// Create a converter Huffman encoding header names and values when it makes them shorter
+    pub fn with_huffman(huffman: bool) -> Self {
+        Self {
+            encoder: Encoder::new(huffman),
+            ..Default::default()
+        }
+    }
+
+    /// Change the size of the HPACK dynamic table, as advertised by the peer with
+    /// SETTINGS_HEADER_TABLE_SIZE. The dynamic table is kept across messages, so the converter
+    /// should be reused for all the messages sent on a connection.
+    pub fn set_max_table_size(&mut self, max_size: usize) {
+        self.encoder.set_max_table_size(max_size);
+    }
+
+    /// Extend the flow-control window, typically when the peer sends a WINDOW_UPDATE
+    pub fn grant(&mut self, increment: usize) {
+        self.window = self.window.saturating_add(increment);
+    }
+
+    /// Encode a header field, returns false and encodes nothing if it doesn't fit in the
+    /// header list size limit
+    fn encode_header(&mut self, name: &[u8], value: &[u8]) -> bool {
+        let size = field_size(name.len(), value.len());
+        if self.header_list_size + size > self.max_header_list_size {
+            return false;
+        }
+        self.header_list_size += size;
+        self.encoder.encode(name, value, &mut self.header_block);
+        true
+    }
+
+    /// Size of the header section starting with block, measured up to the Flags ending it or the
+    /// last parsed block
+    fn section_size<T: AsBuffer>(block: &Block, kawa: &Kawa<T>) -> usize {
+        let buf = kawa.storage.buffer();
+        let block_size = |block: &Block| match block {
+            Block::StatusLine => match &kawa.detached.status_line {
+                StatusLine::Request {
+                    method,
+                    authority,
+                    path,
+                    ..
+                } => {
+                    field_size(7, method.len())
+                        + field_size(7, 4)
+                        + field_size(10, authority.len())
+                        + field_size(5, path.len())
+                }
+                StatusLine::Response { status, .. } => field_size(7, status.len()),
+                StatusLine::Unknown => 0,
+            },
+            Block::Cookies => kawa
+                .detached
+                .jar
+                .iter()
+                .filter(|cookie| !cookie.is_elided())
+                .map(|cookie| {
+                    // the "=" separator is omitted for crumbs without key
+                    let key_len = cookie.key.len();
+                    let separator = usize::from(cookie.has_crumb_separator());
+                    field_size(6, key_len + separator + cookie.val.len())
+                })
+                .sum(),
+            Block::Header(Pair { key, val }) => match key.data_opt(buf) {
+                Some(name) if !is_connection_specific(name, val.data(buf)) => {
+                    field_size(name.len(), val.len())
+                }
+                _ => 0,
+            },
+            _ => 0,
+        };
+        let mut size = block_size(block);
+        for block in kawa.blocks.iter() {
+            if let Block::Flags(Flags {
+                end_header: true, ..
+            }) = block
+            {
+                break;
+            }
+            size += block_size(block);
+        }
+        size
+    }
+
+    /// Push the header of a frame of this stream, its payload must follow
+    pub(crate) fn push_frame_header<T: AsBuffer>(
+        &mut self,
+        kawa: &mut Kawa<T>,
+        kind: FrameType,
+        flags: u8,
+        length: usize,
+    ) {
+        let mut header = Vec::with_capacity(FRAME_HEADER_SIZE);
+        FrameHeader {
+            length,
+            kind,
+            flags,
+            stream_id: self.stream_id,
+        }
+        .write(&mut header);
+        kawa.push_out(Store::from_vec(header));
+    }
+
+    fn push_frame<T: AsBuffer>(
+        &mut self,
+        kawa: &mut Kawa<T>,
+        kind: FrameType,
+        flags: u8,
+        payload: Store,
+    ) {
+        self.push_frame_header(kawa, kind, flags, payload.len());
+        if !payload.is_empty() {
+            kawa.push_out(payload);
+        }
+        self.frames += 1;
+        kawa.push_delimiter();
+    }
+
+    /// Push the header block in a HEADERS frame followed by as many CONTINUATION frames as
+    /// needed. CONTINUATION frames must immediately follow the HEADERS frame, so the frame
+    /// budget is not checked here.
+    fn push_header_block<T: AsBuffer>(&mut self, kawa: &mut Kawa<T>, end_stream: bool) {
+        self.header_list_size = 0;
+        let mut header_block = Store::from_vec(mem::take(&mut self.header_block));
+        let mut kind = FrameType::Headers;
+        let mut flags = if end_stream { END_STREAM } else { 0 };
+        while header_block.len() > self.max_frame_size {
+            let (fragment, rest) = header_block.split(self.max_frame_size);
+            self.push_frame(kawa, kind, flags, fragment);
+            header_block = rest;
+            kind = FrameType::Continuation;
+            flags = 0;
+        }
+        self.push_frame(kawa, kind, flags | END_HEADERS, header_block);
+    }
+
+    /// Returns true if the remaining blocks end the stream without sending anything
+    fn ends_stream<T: AsBuffer>(kawa: &Kawa<T>) -> bool {
+        for block in kawa.blocks.iter() {
+            match block {
+                Block::Flags(Flags {
+                    end_stream: true, ..
+                }) => return true,
+                Block::Flags(_) | Block::ChunkHeader(_) => {}
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    fn budget_exhausted(&self) -> bool {
+        self.max_frames != 0 && self.frames >= self.max_frames
+    }
+}
 
 impl<T: AsBuffer> BlockConverter<T> for H2BlockConverter {
+    fn initialize(&mut self, _kawa: &mut Kawa<T>) {
+        self.frames = 0;
+    }
+
     fn call(&mut self, block: Block, kawa: &mut Kawa<T>) -> bool {
-        match block {
+        if self.budget_exhausted() {
+            kawa.push_block_front(block);
+            return false;
+        }
+        if !self.valid_stream_id(kawa.kind) {
+            kawa.parsing_phase.error("Invalid stream id".into());
+            kawa.push_block_front(block);
+            return false;
+        }
+        let section_start = self.header_list_size == 0
+            && self.max_header_list_size != usize::MAX
+            && matches!(block, Block::StatusLine | Block::Cookies | Block::Header(_));
+        if section_start && Self::section_size(&block, kawa) > self.max_header_list_size {
+            kawa.parsing_phase
+                .error("Header list larger than the peer limit".into());
+            kawa.push_block_front(block);
+            return false;
+        }
+        let buf = kawa.storage.buffer();
+        let fits = match block {
             Block::StatusLine => match kawa.detached.status_line.pop() {
                 StatusLine::Request {
                     method,
@@ -17,95 +320,102 @@ impl<T: AsBuffer> BlockConverter<T> for H2BlockConverter {
                     path,
                     ..
                 } => {
-                    kawa.push_out(Store::Static(b"------------ PSEUDO HEADER\n"));
-                    kawa.push_out(Store::Static(b":method: "));
-                    kawa.push_out(method);
-                    kawa.push_out(Store::Static(b"\n:authority: "));
-                    kawa.push_out(authority);
-                    kawa.push_out(Store::Static(b"\n:path: "));
-                    kawa.push_out(path);
-                    kawa.push_out(Store::Static(b"\n:scheme: http\n"));
+                    self.encode_header(b":method", method.data(buf))
+                        && self.encode_header(b":scheme", b"http")
+                        && self.encode_header(b":authority", authority.data(buf))
+                        && self.encode_header(b":path", path.data(buf))
                 }
                 StatusLine::Response { status, .. } => {
-                    kawa.push_out(Store::Static(b"------------ PSEUDO HEADER\n"));
-                    kawa.push_out(Store::Static(b":status: "));
-                    kawa.push_out(status);
-                    kawa.push_out(Store::Static(b"\n"));
+                    self.encode_header(b":status", status.data(buf))
                 }
                 StatusLine::Unknown => unreachable!(),
             },
             Block::Cookies => {
-                if kawa.detached.jar.is_empty() {
-                    return true;
-                }
-                kawa.push_out(Store::Static(b"------------ HEADER"));
+                // each crumb is sent as a separate cookie field (RFC 7540, 8.1.2.5)
+                let mut crumb = Vec::new();
+                let mut fits = true;
                 for cookie in kawa
                     .detached
                     .jar
                     .drain(..)
                     .filter(|cookie| !cookie.is_elided())
                 {
-                    kawa.out
-                        .push_back(OutBlock::Store(Store::Static(b"\nCookie: ")));
-                    kawa.out.push_back(OutBlock::Store(cookie.key));
-                    kawa.out.push_back(OutBlock::Store(Store::Static(b"=")));
-                    kawa.out.push_back(OutBlock::Store(cookie.val));
+                    crumb.clear();
+                    if cookie.has_crumb_separator() {
+                        crumb.extend_from_slice(cookie.key.data(buf));
+                        crumb.push(b'=');
+                    }
+                    crumb.extend_from_slice(cookie.val.data(buf));
+                    fits = fits && self.encode_header(b"cookie", &crumb);
                 }
-                kawa.push_out(Store::Static(b"\n"));
+                fits
             }
             Block::Header(Pair {
                 key: Store::Empty, ..
             }) => {
                 // elided header
+                true
             }
             Block::Header(Pair { key, val }) => {
-                {
-                    let key = key.data(kawa.storage.buffer());
-                    let val = val.data(kawa.storage.buffer());
-                    if compare_no_case(key, b"connection")
-                        || compare_no_case(key, b"host")
-                        || compare_no_case(key, b"http2-settings")
-                        || compare_no_case(key, b"keep-alive")
-                        || compare_no_case(key, b"proxy-connection")
-                        || compare_no_case(key, b"te") && !compare_no_case(val, b"trailers")
-                        || compare_no_case(key, b"trailer")
-                        || compare_no_case(key, b"transfer-encoding")
-                        || compare_no_case(key, b"upgrade")
-                    {
-                        return true;
-                    }
-                }
-                kawa.push_out(Store::Static(b"------------ HEADER\n"));
-                kawa.push_out(key);
-                kawa.push_out(Store::Static(b": "));
-                kawa.push_out(val);
-                kawa.push_out(Store::Static(b"\n"));
+                let key = key.data(buf);
+                let val = val.data(buf);
+                is_connection_specific(key, val) || self.encode_header(key, val)
             }
             Block::ChunkHeader(_) => {
                 // this converter doesn't align H1 chunks on H2 data frames
+                true
             }
-            Block::Chunk(Chunk { data }) => {
-                kawa.push_out(Store::Static(b"------------ DATA\n"));
-                kawa.push_out(data);
-                kawa.push_out(Store::Static(b"\n"));
-                kawa.push_delimiter()
+            Block::Chunk(Chunk { mut data }) => {
+                let window_exhausted = data.len() > self.window;
+                if window_exhausted {
+                    if self.window == 0 {
+                        kawa.push_block_front(Block::Chunk(Chunk { data }));
+                        return false;
+                    }
+                    let (allowed, rest) = data.split(self.window);
+                    kawa.push_block_front(Block::Chunk(Chunk { data: rest }));
+                    data = allowed;
+                }
+                self.window -= data.len();
+                while data.len() > self.max_frame_size {
+                    let (frame, rest) = data.split(self.max_frame_size);
+                    self.push_frame(kawa, FrameType::Data, 0, frame);
+                    data = rest;
+                    if self.budget_exhausted() {
+                        self.window += data.len();
+                        kawa.push_block_front(Block::Chunk(Chunk { data }));
+                        return false;
+                    }
+                }
+                self.stream_ended = !window_exhausted && Self::ends_stream(kawa);
+                let flags = if self.stream_ended { END_STREAM } else { 0 };
+                self.push_frame(kawa, FrameType::Data, flags, data);
+                if window_exhausted {
+                    return false;
+                }
+                true
             }
             Block::Flags(Flags {
                 end_header,
                 end_stream,
                 ..
             }) => {
-                if end_header {
-                    kawa.push_out(Store::Static(b"------------ END HEADER\n"));
+                // an empty trailer section is not sent
+                if end_header && !self.header_block.is_empty() {
+                    self.push_header_block(kawa, end_stream);
+                } else if end_stream && !self.stream_ended {
+                    self.push_frame(kawa, FrameType::Data, END_STREAM, Store::Empty);
                 }
                 if end_stream {
-                    kawa.push_out(Store::Static(b"------------ END STREAM\n"));
-                }
-                if end_header || end_stream {
-                    kawa.push_delimiter()
+                    self.stream_ended = false;
                 }
+                true
             }
+        };
+        if !fits {
+            kawa.parsing_phase
+                .error("Header list larger than the peer limit".into());
         }
-        true
+        fits
     }
 }
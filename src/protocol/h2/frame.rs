@@ -0,0 +1,185 @@
+use nom::{
+    number::streaming::{be_u24, be_u32, be_u8},
+    IResult,
+};
+
+use crate::storage::Store;
+
+/// Size of the header preceding every HTTP/2 frame payload
+pub const FRAME_HEADER_SIZE: usize = 9;
+/// Initial value of SETTINGS_MAX_FRAME_SIZE
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16384;
+/// Initial value of SETTINGS_HEADER_TABLE_SIZE
+pub const DEFAULT_HEADER_TABLE_SIZE: usize = 4096;
+/// Initial value of SETTINGS_INITIAL_WINDOW_SIZE
+pub const DEFAULT_INITIAL_WINDOW_SIZE: usize = 65535;
+
+/// Connection preface sent by a client before its SETTINGS frame (RFC 9113, 3.4)
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+pub const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+pub const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+pub const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+pub const END_STREAM: u8 = 0x1;
+pub const ACK: u8 = 0x1;
+pub const END_HEADERS: u8 = 0x4;
+pub const PADDED: u8 = 0x8;
+pub const PRIORITY: u8 = 0x20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Data,
+    Headers,
+    Priority,
+    RstStream,
+    Settings,
+    PushPromise,
+    Ping,
+    GoAway,
+    WindowUpdate,
+    Continuation,
+    Unknown(u8),
+}
+
+impl From<u8> for FrameType {
+    fn from(kind: u8) -> Self {
+        match kind {
+            0x0 => FrameType::Data,
+            0x1 => FrameType::Headers,
+            0x2 => FrameType::Priority,
+            0x3 => FrameType::RstStream,
+            0x4 => FrameType::Settings,
+            0x5 => FrameType::PushPromise,
+            0x6 => FrameType::Ping,
+            0x7 => FrameType::GoAway,
+            0x8 => FrameType::WindowUpdate,
+            0x9 => FrameType::Continuation,
+            kind => FrameType::Unknown(kind),
+        }
+    }
+}
+
+impl FrameType {
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            FrameType::Data => 0x0,
+            FrameType::Headers => 0x1,
+            FrameType::Priority => 0x2,
+            FrameType::RstStream => 0x3,
+            FrameType::Settings => 0x4,
+            FrameType::PushPromise => 0x5,
+            FrameType::Ping => 0x6,
+            FrameType::GoAway => 0x7,
+            FrameType::WindowUpdate => 0x8,
+            FrameType::Continuation => 0x9,
+            FrameType::Unknown(kind) => *kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub length: usize,
+    pub kind: FrameType,
+    pub flags: u8,
+    pub stream_id: u32,
+}
+
+impl FrameHeader {
+    /// serialize the 9 bytes header of an HTTP/2 frame
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.length as u32).to_be_bytes()[1..]);
+        out.push(self.kind.as_u8());
+        out.push(self.flags);
+        out.extend_from_slice(&(self.stream_id & 0x7FFF_FFFF).to_be_bytes());
+    }
+}
+
+/// parse the 9 bytes header of an HTTP/2 frame
+pub fn frame_header(i: &[u8]) -> IResult<&[u8], FrameHeader> {
+    let (i, length) = be_u24(i)?;
+    let (i, kind) = be_u8(i)?;
+    let (i, flags) = be_u8(i)?;
+    let (i, stream_id) = be_u32(i)?;
+    Ok((
+        i,
+        FrameHeader {
+            length: length as usize,
+            kind: kind.into(),
+            flags,
+            stream_id: stream_id & 0x7FFF_FFFF,
+        },
+    ))
+}
+
+/// Remove the padding (and the priority fields of HEADERS frames) from a frame payload.
+/// Returns None if the padding length is invalid.
+pub fn strip_padding<'a>(header: &FrameHeader, payload: &'a [u8]) -> Option<&'a [u8]> {
+    let mut payload = payload;
+    let mut padding = 0;
+    if header.flags & PADDED != 0 {
+        let (length, rest) = payload.split_first()?;
+        padding = *length as usize;
+        payload = rest;
+    }
+    if header.kind == FrameType::Headers && header.flags & PRIORITY != 0 {
+        payload = payload.get(5..)?;
+    }
+    if padding > payload.len() {
+        return None;
+    }
+    Some(&payload[..payload.len() - padding])
+}
+
+/// Settings advertised in the SETTINGS frame following the connection preface.
+///
+/// note: max_frame_size must be between 16384 and 16777215 (RFC 9113, 6.5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub header_table_size: usize,
+    pub initial_window_size: usize,
+    pub max_frame_size: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            header_table_size: DEFAULT_HEADER_TABLE_SIZE,
+            initial_window_size: DEFAULT_INITIAL_WINDOW_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl Settings {
+    /// serialize a SETTINGS frame carrying every setting
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let settings = [
+            (SETTINGS_HEADER_TABLE_SIZE, self.header_table_size),
+            (SETTINGS_INITIAL_WINDOW_SIZE, self.initial_window_size),
+            (SETTINGS_MAX_FRAME_SIZE, self.max_frame_size),
+        ];
+        FrameHeader {
+            length: settings.len() * 6,
+            kind: FrameType::Settings,
+            flags: 0,
+            stream_id: 0,
+        }
+        .write(out);
+        for (identifier, value) in settings {
+            out.extend_from_slice(&identifier.to_be_bytes());
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+    }
+}
+
+/// Stores of the connection preface of a cleartext HTTP/2 client followed by its initial
+/// SETTINGS frame. They should be pushed in the out vector of the first message of the
+/// connection, before any of its frames.
+pub fn preface(settings: &Settings) -> Vec<Store> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + 18);
+    settings.write(&mut frame);
+    vec![Store::Static(PREFACE), Store::from_vec(frame)]
+}
@@ -0,0 +1,113 @@
+use std::cmp::min;
+
+use crate::{
+    protocol::h2::{converter::H2BlockConverter, frame::FrameType},
+    storage::{AsBuffer, Block, BlockConverter, Chunk, Kawa, Store},
+};
+
+/// Size of the prefix of a gRPC message: a compressed flag and a big endian 32 bits length
+pub const GRPC_PREFIX_SIZE: usize = 5;
+
+/// gRPC converter, DATA frames are aligned on length-prefixed gRPC messages and each delimiter
+/// pushed in the out vector marks the end of a message instead of the end of a frame.
+///
+/// Each message is sent in its own DATA frame, or in several frames if it is larger than the
+/// max_frame_size of the inner converter. Message payloads are not copied, a Chunk may hold
+/// several messages or only a part of one, in which case the converter remembers how much of
+/// the message (or of its prefix) is missing until the next Chunk. The status line, headers and
+/// flags are handled by an H2BlockConverter, whose flow-control window also limits the DATA
+/// frames.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcBlockConverter {
+    pub inner: H2BlockConverter,
+    prefix: [u8; GRPC_PREFIX_SIZE],
+    prefix_len: usize,
+    /// payload bytes missing to complete the current message
+    remaining: usize,
+    /// payload bytes missing to complete the current frame
+    frame_remaining: usize,
+}
+
+impl GrpcBlockConverter {
+    /// Returns true if the last converted Chunk ended in the middle of a message
+    pub fn has_partial_message(&self) -> bool {
+        self.prefix_len != 0 || self.remaining != 0
+    }
+
+    fn push_data_frame_header<T: AsBuffer>(&mut self, kawa: &mut Kawa<T>, length: usize) {
+        let length = min(min(length, self.inner.max_frame_size), self.inner.window);
+        self.inner
+            .push_frame_header(kawa, FrameType::Data, 0, length);
+        self.inner.window -= length;
+        self.frame_remaining = length;
+    }
+
+    /// Returns false if the flow-control window is exhausted, the rest of the Chunk is put back
+    /// in front of the blocks.
+    fn convert_chunk<T: AsBuffer>(&mut self, kawa: &mut Kawa<T>, mut data: Store) -> bool {
+        let mut len = data.len();
+        while len > 0 {
+            let window_exhausted = if self.remaining == 0 {
+                // the prefix is sent with the start of the message, in a single frame
+                self.prefix_len == 0 && self.inner.window < GRPC_PREFIX_SIZE
+            } else {
+                self.frame_remaining == 0 && self.inner.window == 0
+            };
+            if window_exhausted {
+                kawa.push_block_front(Block::Chunk(Chunk { data }));
+                return false;
+            }
+            if self.remaining == 0 {
+                // the prefix may be split across several chunks, it is copied
+                let taken = min(GRPC_PREFIX_SIZE - self.prefix_len, len);
+                let bytes = &data.data(kawa.storage.buffer())[..taken];
+                self.prefix[self.prefix_len..self.prefix_len + taken].copy_from_slice(bytes);
+                self.prefix_len += taken;
+                data = data.split(taken).1;
+                len -= taken;
+                if self.prefix_len < GRPC_PREFIX_SIZE {
+                    continue;
+                }
+                self.prefix_len = 0;
+                let mut length = [0; 4];
+                length.copy_from_slice(&self.prefix[1..]);
+                self.remaining = u32::from_be_bytes(length) as usize;
+                self.push_data_frame_header(kawa, GRPC_PREFIX_SIZE + self.remaining);
+                kawa.push_out(Store::from_slice(&self.prefix));
+                self.frame_remaining -= GRPC_PREFIX_SIZE;
+            } else {
+                if self.frame_remaining == 0 {
+                    self.push_data_frame_header(kawa, self.remaining);
+                }
+                let taken = min(min(self.remaining, self.frame_remaining), len);
+                let (payload, rest) = data.split(taken);
+                kawa.push_out(payload);
+                data = rest;
+                len -= taken;
+                self.remaining -= taken;
+                self.frame_remaining -= taken;
+            }
+            if self.remaining == 0 {
+                kawa.push_delimiter();
+            }
+        }
+        true
+    }
+}
+
+impl<T: AsBuffer> BlockConverter<T> for GrpcBlockConverter {
+    fn initialize(&mut self, kawa: &mut Kawa<T>) {
+        self.inner.initialize(kawa);
+    }
+
+    fn call(&mut self, block: Block, kawa: &mut Kawa<T>) -> bool {
+        match block {
+            Block::Chunk(Chunk { data }) => self.convert_chunk(kawa, data),
+            block => self.inner.call(block, kawa),
+        }
+    }
+
+    fn finalize(&mut self, kawa: &mut Kawa<T>) {
+        self.inner.finalize(kawa);
+    }
+}
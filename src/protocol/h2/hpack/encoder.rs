@@ -0,0 +1,121 @@
+use std::borrow::Cow;
+
+use crate::protocol::h2::hpack::{
+    huffman, table::find_static, DynamicTable, DEFAULT_TABLE_SIZE, STATIC_TABLE,
+};
+
+/// Encode an integer with a N-bit prefix (RFC 7541, 5.1), flags holds the bits preceding the
+/// prefix in the first byte.
+pub fn encode_integer(value: usize, prefix: u8, flags: u8, out: &mut Vec<u8>) {
+    let mask = ((1u16 << prefix) - 1) as usize;
+    if value < mask {
+        out.push(flags | value as u8);
+        return;
+    }
+    out.push(flags | mask as u8);
+    let mut value = value - mask;
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Encode a string literal (RFC 7541, 5.2). When huffman is set, the Huffman form is used unless
+/// it is longer than the raw form.
+pub fn encode_string(data: &[u8], huffman: bool, out: &mut Vec<u8>) {
+    if huffman {
+        let length = huffman::encoded_len(data);
+        if length < data.len() {
+            encode_integer(length, 7, 0x80, out);
+            huffman::encode(data, out);
+            return;
+        }
+    }
+    encode_integer(data.len(), 7, 0, out);
+    out.extend_from_slice(data);
+}
+
+/// HPACK encoder, it produces header blocks from header fields. It holds the dynamic table, so a
+/// single encoder should be used for all the header blocks sent on a connection.
+///
+/// note: header names are lowercased, as required by HTTP/2.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    /// Huffman encode the names and values, when it makes them shorter
+    pub huffman: bool,
+    pub table: DynamicTable,
+    /// Smallest table size set since the last header block, if it was changed
+    size_update: Option<usize>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl Encoder {
+    pub fn new(huffman: bool) -> Self {
+        Self {
+            huffman,
+            table: DynamicTable::new(DEFAULT_TABLE_SIZE),
+            size_update: None,
+        }
+    }
+
+    /// Change the size of the dynamic table, evicting entries if necessary. The dynamic table
+    /// size update is emitted at the beginning of the next header block (RFC 7541, 4.2), so it
+    /// should not be called in the middle of a header block.
+    pub fn set_max_table_size(&mut self, max_size: usize) {
+        self.size_update = Some(match self.size_update {
+            Some(previous) => previous.min(max_size),
+            None => max_size,
+        });
+        self.table.set_max_size(max_size);
+    }
+
+    fn encode_size_update(&mut self, out: &mut Vec<u8>) {
+        if let Some(smallest) = self.size_update.take() {
+            if smallest < self.table.max_size() {
+                encode_integer(smallest, 5, 0x20, out);
+            }
+            encode_integer(self.table.max_size(), 5, 0x20, out);
+        }
+    }
+
+    /// Encode a header field. A field already in the static or dynamic table is sent as an
+    /// index, others are sent as literals with incremental indexing, using the index of their
+    /// name when it is in one of the tables (RFC 7541, 6.1 and 6.2.1).
+    pub fn encode(&mut self, name: &[u8], value: &[u8], out: &mut Vec<u8>) {
+        self.encode_size_update(out);
+        let name = if name.iter().any(u8::is_ascii_uppercase) {
+            Cow::Owned(name.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        };
+        let static_match = find_static(&name, value);
+        if let Some((index, true)) = static_match {
+            encode_integer(index, 7, 0x80, out);
+            return;
+        }
+        match (self.table.find(&name, value), static_match) {
+            (Some((index, true)), _) => {
+                encode_integer(STATIC_TABLE.len() + 1 + index, 7, 0x80, out);
+                return;
+            }
+            (_, Some((index, _))) => {
+                encode_integer(index, 6, 0x40, out);
+            }
+            (Some((index, _)), None) => {
+                encode_integer(STATIC_TABLE.len() + 1 + index, 6, 0x40, out);
+            }
+            (None, None) => {
+                out.push(0x40);
+                encode_string(&name, self.huffman, out);
+            }
+        }
+        encode_string(value, self.huffman, out);
+        self.table.insert(&name, value);
+    }
+}
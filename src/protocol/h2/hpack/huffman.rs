@@ -0,0 +1,371 @@
+/// Huffman codes of RFC 7541 (Appendix B), indexed by symbol.
+/// Each entry holds the code (right aligned) and its length in bits.
+#[rustfmt::skip]
+pub const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13), // 0x00
+    (0x7fffd8, 23), // 0x01
+    (0xfffffe2, 28), // 0x02
+    (0xfffffe3, 28), // 0x03
+    (0xfffffe4, 28), // 0x04
+    (0xfffffe5, 28), // 0x05
+    (0xfffffe6, 28), // 0x06
+    (0xfffffe7, 28), // 0x07
+    (0xfffffe8, 28), // 0x08
+    (0xffffea, 24), // 0x09
+    (0x3ffffffc, 30), // 0x0A
+    (0xfffffe9, 28), // 0x0B
+    (0xfffffea, 28), // 0x0C
+    (0x3ffffffd, 30), // 0x0D
+    (0xfffffeb, 28), // 0x0E
+    (0xfffffec, 28), // 0x0F
+    (0xfffffed, 28), // 0x10
+    (0xfffffee, 28), // 0x11
+    (0xfffffef, 28), // 0x12
+    (0xffffff0, 28), // 0x13
+    (0xffffff1, 28), // 0x14
+    (0xffffff2, 28), // 0x15
+    (0x3ffffffe, 30), // 0x16
+    (0xffffff3, 28), // 0x17
+    (0xffffff4, 28), // 0x18
+    (0xffffff5, 28), // 0x19
+    (0xffffff6, 28), // 0x1A
+    (0xffffff7, 28), // 0x1B
+    (0xffffff8, 28), // 0x1C
+    (0xffffff9, 28), // 0x1D
+    (0xffffffa, 28), // 0x1E
+    (0xffffffb, 28), // 0x1F
+    (0x14, 6), // 0x20
+    (0x3f8, 10), // '!'
+    (0x3f9, 10), // '"'
+    (0xffa, 12), // '#'
+    (0x1ff9, 13), // '$'
+    (0x15, 6), // '%'
+    (0xf8, 8), // '&'
+    (0x7fa, 11), // "'"
+    (0x3fa, 10), // '('
+    (0x3fb, 10), // ')'
+    (0xf9, 8), // '*'
+    (0x7fb, 11), // '+'
+    (0xfa, 8), // ','
+    (0x16, 6), // '-'
+    (0x17, 6), // '.'
+    (0x18, 6), // '/'
+    (0x0, 5), // '0'
+    (0x1, 5), // '1'
+    (0x2, 5), // '2'
+    (0x19, 6), // '3'
+    (0x1a, 6), // '4'
+    (0x1b, 6), // '5'
+    (0x1c, 6), // '6'
+    (0x1d, 6), // '7'
+    (0x1e, 6), // '8'
+    (0x1f, 6), // '9'
+    (0x5c, 7), // ':'
+    (0xfb, 8), // ';'
+    (0x7ffc, 15), // '<'
+    (0x20, 6), // '='
+    (0xffb, 12), // '>'
+    (0x3fc, 10), // '?'
+    (0x1ffa, 13), // '@'
+    (0x21, 6), // 'A'
+    (0x5d, 7), // 'B'
+    (0x5e, 7), // 'C'
+    (0x5f, 7), // 'D'
+    (0x60, 7), // 'E'
+    (0x61, 7), // 'F'
+    (0x62, 7), // 'G'
+    (0x63, 7), // 'H'
+    (0x64, 7), // 'I'
+    (0x65, 7), // 'J'
+    (0x66, 7), // 'K'
+    (0x67, 7), // 'L'
+    (0x68, 7), // 'M'
+    (0x69, 7), // 'N'
+    (0x6a, 7), // 'O'
+    (0x6b, 7), // 'P'
+    (0x6c, 7), // 'Q'
+    (0x6d, 7), // 'R'
+    (0x6e, 7), // 'S'
+    (0x6f, 7), // 'T'
+    (0x70, 7), // 'U'
+    (0x71, 7), // 'V'
+    (0x72, 7), // 'W'
+    (0xfc, 8), // 'X'
+    (0x73, 7), // 'Y'
+    (0xfd, 8), // 'Z'
+    (0x1ffb, 13), // '['
+    (0x7fff0, 19), // '\\'
+    (0x1ffc, 13), // ']'
+    (0x3ffc, 14), // '^'
+    (0x22, 6), // '_'
+    (0x7ffd, 15), // '`'
+    (0x3, 5), // 'a'
+    (0x23, 6), // 'b'
+    (0x4, 5), // 'c'
+    (0x24, 6), // 'd'
+    (0x5, 5), // 'e'
+    (0x25, 6), // 'f'
+    (0x26, 6), // 'g'
+    (0x27, 6), // 'h'
+    (0x6, 5), // 'i'
+    (0x74, 7), // 'j'
+    (0x75, 7), // 'k'
+    (0x28, 6), // 'l'
+    (0x29, 6), // 'm'
+    (0x2a, 6), // 'n'
+    (0x7, 5), // 'o'
+    (0x2b, 6), // 'p'
+    (0x76, 7), // 'q'
+    (0x2c, 6), // 'r'
+    (0x8, 5), // 's'
+    (0x9, 5), // 't'
+    (0x2d, 6), // 'u'
+    (0x77, 7), // 'v'
+    (0x78, 7), // 'w'
+    (0x79, 7), // 'x'
+    (0x7a, 7), // 'y'
+    (0x7b, 7), // 'z'
+    (0x7ffe, 15), // '{'
+    (0x7fc, 11), // '|'
+    (0x3ffd, 14), // '}'
+    (0x1ffd, 13), // '~'
+    (0xffffffc, 28), // 0x7F
+    (0xfffe6, 20), // 0x80
+    (0x3fffd2, 22), // 0x81
+    (0xfffe7, 20), // 0x82
+    (0xfffe8, 20), // 0x83
+    (0x3fffd3, 22), // 0x84
+    (0x3fffd4, 22), // 0x85
+    (0x3fffd5, 22), // 0x86
+    (0x7fffd9, 23), // 0x87
+    (0x3fffd6, 22), // 0x88
+    (0x7fffda, 23), // 0x89
+    (0x7fffdb, 23), // 0x8A
+    (0x7fffdc, 23), // 0x8B
+    (0x7fffdd, 23), // 0x8C
+    (0x7fffde, 23), // 0x8D
+    (0xffffeb, 24), // 0x8E
+    (0x7fffdf, 23), // 0x8F
+    (0xffffec, 24), // 0x90
+    (0xffffed, 24), // 0x91
+    (0x3fffd7, 22), // 0x92
+    (0x7fffe0, 23), // 0x93
+    (0xffffee, 24), // 0x94
+    (0x7fffe1, 23), // 0x95
+    (0x7fffe2, 23), // 0x96
+    (0x7fffe3, 23), // 0x97
+    (0x7fffe4, 23), // 0x98
+    (0x1fffdc, 21), // 0x99
+    (0x3fffd8, 22), // 0x9A
+    (0x7fffe5, 23), // 0x9B
+    (0x3fffd9, 22), // 0x9C
+    (0x7fffe6, 23), // 0x9D
+    (0x7fffe7, 23), // 0x9E
+    (0xffffef, 24), // 0x9F
+    (0x3fffda, 22), // 0xA0
+    (0x1fffdd, 21), // 0xA1
+    (0xfffe9, 20), // 0xA2
+    (0x3fffdb, 22), // 0xA3
+    (0x3fffdc, 22), // 0xA4
+    (0x7fffe8, 23), // 0xA5
+    (0x7fffe9, 23), // 0xA6
+    (0x1fffde, 21), // 0xA7
+    (0x7fffea, 23), // 0xA8
+    (0x3fffdd, 22), // 0xA9
+    (0x3fffde, 22), // 0xAA
+    (0xfffff0, 24), // 0xAB
+    (0x1fffdf, 21), // 0xAC
+    (0x3fffdf, 22), // 0xAD
+    (0x7fffeb, 23), // 0xAE
+    (0x7fffec, 23), // 0xAF
+    (0x1fffe0, 21), // 0xB0
+    (0x1fffe1, 21), // 0xB1
+    (0x3fffe0, 22), // 0xB2
+    (0x1fffe2, 21), // 0xB3
+    (0x7fffed, 23), // 0xB4
+    (0x3fffe1, 22), // 0xB5
+    (0x7fffee, 23), // 0xB6
+    (0x7fffef, 23), // 0xB7
+    (0xfffea, 20), // 0xB8
+    (0x3fffe2, 22), // 0xB9
+    (0x3fffe3, 22), // 0xBA
+    (0x3fffe4, 22), // 0xBB
+    (0x7ffff0, 23), // 0xBC
+    (0x3fffe5, 22), // 0xBD
+    (0x3fffe6, 22), // 0xBE
+    (0x7ffff1, 23), // 0xBF
+    (0x3ffffe0, 26), // 0xC0
+    (0x3ffffe1, 26), // 0xC1
+    (0xfffeb, 20), // 0xC2
+    (0x7fff1, 19), // 0xC3
+    (0x3fffe7, 22), // 0xC4
+    (0x7ffff2, 23), // 0xC5
+    (0x3fffe8, 22), // 0xC6
+    (0x1ffffec, 25), // 0xC7
+    (0x3ffffe2, 26), // 0xC8
+    (0x3ffffe3, 26), // 0xC9
+    (0x3ffffe4, 26), // 0xCA
+    (0x7ffffde, 27), // 0xCB
+    (0x7ffffdf, 27), // 0xCC
+    (0x3ffffe5, 26), // 0xCD
+    (0xfffff1, 24), // 0xCE
+    (0x1ffffed, 25), // 0xCF
+    (0x7fff2, 19), // 0xD0
+    (0x1fffe3, 21), // 0xD1
+    (0x3ffffe6, 26), // 0xD2
+    (0x7ffffe0, 27), // 0xD3
+    (0x7ffffe1, 27), // 0xD4
+    (0x3ffffe7, 26), // 0xD5
+    (0x7ffffe2, 27), // 0xD6
+    (0xfffff2, 24), // 0xD7
+    (0x1fffe4, 21), // 0xD8
+    (0x1fffe5, 21), // 0xD9
+    (0x3ffffe8, 26), // 0xDA
+    (0x3ffffe9, 26), // 0xDB
+    (0xffffffd, 28), // 0xDC
+    (0x7ffffe3, 27), // 0xDD
+    (0x7ffffe4, 27), // 0xDE
+    (0x7ffffe5, 27), // 0xDF
+    (0xfffec, 20), // 0xE0
+    (0xfffff3, 24), // 0xE1
+    (0xfffed, 20), // 0xE2
+    (0x1fffe6, 21), // 0xE3
+    (0x3fffe9, 22), // 0xE4
+    (0x1fffe7, 21), // 0xE5
+    (0x1fffe8, 21), // 0xE6
+    (0x7ffff3, 23), // 0xE7
+    (0x3fffea, 22), // 0xE8
+    (0x3fffeb, 22), // 0xE9
+    (0x1ffffee, 25), // 0xEA
+    (0x1ffffef, 25), // 0xEB
+    (0xfffff4, 24), // 0xEC
+    (0xfffff5, 24), // 0xED
+    (0x3ffffea, 26), // 0xEE
+    (0x7ffff4, 23), // 0xEF
+    (0x3ffffeb, 26), // 0xF0
+    (0x7ffffe6, 27), // 0xF1
+    (0x3ffffec, 26), // 0xF2
+    (0x3ffffed, 26), // 0xF3
+    (0x7ffffe7, 27), // 0xF4
+    (0x7ffffe8, 27), // 0xF5
+    (0x7ffffe9, 27), // 0xF6
+    (0x7ffffea, 27), // 0xF7
+    (0x7ffffeb, 27), // 0xF8
+    (0xffffffe, 28), // 0xF9
+    (0x7ffffec, 27), // 0xFA
+    (0x7ffffed, 27), // 0xFB
+    (0x7ffffee, 27), // 0xFC
+    (0x7ffffef, 27), // 0xFD
+    (0x7fffff0, 27), // 0xFE
+    (0x3ffffee, 26), // 0xFF
+    (0x3fffffff, 30), // EOS
+];
+
+pub const EOS: u16 = 256;
+const MAX_CODE_LENGTH: usize = 30;
+
+/// The HPACK Huffman code is canonical: codes of the same length are consecutive and sorted by
+/// symbol. Decoding only needs, for each length, the first code and where its symbols start.
+struct DecodeTable {
+    first_code: [u32; MAX_CODE_LENGTH + 1],
+    first_index: [u16; MAX_CODE_LENGTH + 1],
+    count: [u16; MAX_CODE_LENGTH + 1],
+    symbols: [u16; 257],
+}
+
+const fn build_decode_table() -> DecodeTable {
+    let mut table = DecodeTable {
+        first_code: [0; MAX_CODE_LENGTH + 1],
+        first_index: [0; MAX_CODE_LENGTH + 1],
+        count: [0; MAX_CODE_LENGTH + 1],
+        symbols: [0; 257],
+    };
+    let mut index = 0;
+    let mut length = 1;
+    while length <= MAX_CODE_LENGTH {
+        table.first_index[length] = index as u16;
+        let mut symbol = 0;
+        while symbol < HUFFMAN_CODES.len() {
+            let (code, code_length) = HUFFMAN_CODES[symbol];
+            if code_length as usize == length {
+                if table.count[length] == 0 {
+                    table.first_code[length] = code;
+                }
+                table.count[length] += 1;
+                table.symbols[index] = symbol as u16;
+                index += 1;
+            }
+            symbol += 1;
+        }
+        length += 1;
+    }
+    table
+}
+
+const DECODE_TABLE: DecodeTable = build_decode_table();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuffmanError {
+    /// The EOS symbol must not appear in a Huffman encoded string
+    EndOfString,
+    /// The padding is longer than 7 bits or is not made of the most significant bits of EOS
+    InvalidPadding,
+}
+
+/// Decode a Huffman encoded string, appending the decoded bytes to `out`.
+pub fn decode(input: &[u8], out: &mut Vec<u8>) -> Result<(), HuffmanError> {
+    let mut code: u32 = 0;
+    let mut length = 0;
+    for byte in input {
+        for shift in (0..8).rev() {
+            code = (code << 1) | ((*byte >> shift) & 1) as u32;
+            length += 1;
+            if length > MAX_CODE_LENGTH {
+                return Err(HuffmanError::EndOfString);
+            }
+            let count = DECODE_TABLE.count[length] as u32;
+            let first_code = DECODE_TABLE.first_code[length];
+            if count > 0 && code >= first_code && code - first_code < count {
+                let index = DECODE_TABLE.first_index[length] as u32 + code - first_code;
+                let symbol = DECODE_TABLE.symbols[index as usize];
+                if symbol == EOS {
+                    return Err(HuffmanError::EndOfString);
+                }
+                out.push(symbol as u8);
+                code = 0;
+                length = 0;
+            }
+        }
+    }
+    if length > 7 || code != (1 << length) - 1 {
+        return Err(HuffmanError::InvalidPadding);
+    }
+    Ok(())
+}
+
+/// Length in bytes of the Huffman encoded form of input
+pub fn encoded_len(input: &[u8]) -> usize {
+    let bits: usize = input
+        .iter()
+        .map(|byte| HUFFMAN_CODES[*byte as usize].1 as usize)
+        .sum();
+    (bits + 7) / 8
+}
+
+/// Huffman encode input, the last byte is padded with the most significant bits of EOS
+pub fn encode(input: &[u8], out: &mut Vec<u8>) {
+    let mut bits: u64 = 0;
+    let mut length = 0;
+    for byte in input {
+        let (code, code_length) = HUFFMAN_CODES[*byte as usize];
+        bits = (bits << code_length) | code as u64;
+        length += code_length;
+        while length >= 8 {
+            length -= 8;
+            out.push((bits >> length) as u8);
+        }
+    }
+    if length > 0 {
+        out.push(((bits << (8 - length)) | (0xFF >> length)) as u8);
+    }
+}
@@ -0,0 +1,456 @@
+pub mod encoder;
+pub mod huffman;
+pub mod table;
+
+pub use encoder::Encoder;
+pub use table::{DynamicTable, STATIC_TABLE};
+
+use crate::storage::Store;
+
+/// Initial size of the dynamic table (SETTINGS_HEADER_TABLE_SIZE default value)
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecoderError {
+    Truncated,
+    IntegerOverflow,
+    InvalidIndex,
+    InvalidHuffman,
+    InvalidTableSizeUpdate,
+}
+
+impl DecoderError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecoderError::Truncated => "Truncated HPACK header block",
+            DecoderError::IntegerOverflow => "HPACK integer overflow",
+            DecoderError::InvalidIndex => "Invalid HPACK index",
+            DecoderError::InvalidHuffman => "Invalid HPACK Huffman string",
+            DecoderError::InvalidTableSizeUpdate => "Invalid HPACK dynamic table size update",
+        }
+    }
+}
+
+/// Decode an integer with a N-bit prefix (RFC 7541, 5.1)
+pub fn decode_integer(input: &[u8], prefix: u8) -> Result<(&[u8], usize), DecoderError> {
+    let mask = ((1u16 << prefix) - 1) as u8;
+    let (first, mut input) = match input.split_first() {
+        Some((first, input)) => (*first & mask, input),
+        None => return Err(DecoderError::Truncated),
+    };
+    if first < mask {
+        return Ok((input, first as usize));
+    }
+    let mut value = mask as usize;
+    let mut shift = 0;
+    loop {
+        let (byte, rest) = match input.split_first() {
+            Some((byte, rest)) => (*byte, rest),
+            None => return Err(DecoderError::Truncated),
+        };
+        input = rest;
+        if shift > 28 {
+            return Err(DecoderError::IntegerOverflow);
+        }
+        value += ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok((input, value));
+        }
+    }
+}
+
+enum StringLiteral<'a> {
+    Raw(&'a [u8]),
+    Huffman(Vec<u8>),
+}
+
+impl<'a> StringLiteral<'a> {
+    fn data(&self) -> &[u8] {
+        match self {
+            StringLiteral::Raw(data) => data,
+            StringLiteral::Huffman(data) => data,
+        }
+    }
+    fn into_store(self, buffer: &[u8]) -> Store {
+        match self {
+            StringLiteral::Raw(data) => Store::new_slice(buffer, data),
+            StringLiteral::Huffman(data) => Store::from_vec(data),
+        }
+    }
+}
+
+/// Decode a string literal (RFC 7541, 5.2)
+fn decode_string(input: &[u8]) -> Result<(&[u8], StringLiteral<'_>), DecoderError> {
+    let huffman = input.first().map_or(false, |byte| byte & 0x80 != 0);
+    let (input, length) = decode_integer(input, 7)?;
+    if input.len() < length {
+        return Err(DecoderError::Truncated);
+    }
+    let (data, input) = input.split_at(length);
+    if huffman {
+        let mut decoded = Vec::with_capacity(length * 8 / 5);
+        huffman::decode(data, &mut decoded).map_err(|_| DecoderError::InvalidHuffman)?;
+        Ok((input, StringLiteral::Huffman(decoded)))
+    } else {
+        Ok((input, StringLiteral::Raw(data)))
+    }
+}
+
+/// HPACK decoder, it holds the dynamic table which is shared by all the header blocks received
+/// on a connection.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    pub table: DynamicTable,
+    /// Upper bound of the dynamic table size, as advertised with SETTINGS_HEADER_TABLE_SIZE
+    pub max_table_size: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::with_max_table_size(DEFAULT_TABLE_SIZE)
+    }
+
+    pub fn with_max_table_size(max_table_size: usize) -> Self {
+        Self {
+            table: DynamicTable::new(max_table_size),
+            max_table_size,
+        }
+    }
+
+    fn get(&self, index: usize) -> Result<(Store, Store), DecoderError> {
+        match index {
+            0 => Err(DecoderError::InvalidIndex),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((Store::Static(name), Store::Static(value)))
+            }
+            _ => match self.table.get(index - 62) {
+                Some((name, value)) => Ok((Store::from_slice(name), Store::from_slice(value))),
+                None => Err(DecoderError::InvalidIndex),
+            },
+        }
+    }
+
+    /// Decode a complete header block, calling `emit` for each header field in order.
+    ///
+    /// note: `block` must be a subslice of `buffer`. Raw string literals are not copied, they are
+    /// referenced with Store::Slices relative to `buffer`. Huffman encoded literals and entries
+    /// of the dynamic table are allocated.
+    pub fn decode<F: FnMut(Store, Store)>(
+        &mut self,
+        buffer: &[u8],
+        mut block: &[u8],
+        mut emit: F,
+    ) -> Result<(), DecoderError> {
+        let mut first = true;
+        while let Some(&byte) = block.first() {
+            if byte & 0x80 != 0 {
+                // indexed header field
+                let (i, index) = decode_integer(block, 7)?;
+                let (name, value) = self.get(index)?;
+                emit(name, value);
+                block = i;
+            } else if byte & 0xE0 == 0x20 {
+                // dynamic table size update, only allowed at the beginning of a block
+                let (i, size) = decode_integer(block, 5)?;
+                if !first || size > self.max_table_size {
+                    return Err(DecoderError::InvalidTableSizeUpdate);
+                }
+                self.table.set_max_size(size);
+                block = i;
+                continue;
+            } else {
+                // literal header field, with incremental indexing (6-bit prefix),
+                // without indexing or never indexed (4-bit prefix)
+                let indexing = byte & 0xC0 == 0x40;
+                let prefix = if indexing { 6 } else { 4 };
+                let (i, index) = decode_integer(block, prefix)?;
+                let (i, name) = if index == 0 {
+                    let (i, name) = decode_string(i)?;
+                    (i, Some(name))
+                } else {
+                    (i, None)
+                };
+                let (i, value) = decode_string(i)?;
+                let name = match name {
+                    Some(name) => {
+                        if indexing {
+                            self.table.insert(name.data(), value.data());
+                        }
+                        name.into_store(buffer)
+                    }
+                    None => {
+                        let (name, _) = self.get(index)?;
+                        if indexing {
+                            self.table.insert(name.data(buffer), value.data());
+                        }
+                        name
+                    }
+                };
+                emit(name, value.into_store(buffer));
+                block = i;
+            }
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_block(decoder: &mut Decoder, block: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut fields = Vec::new();
+        decoder
+            .decode(block, block, |key, val| {
+                fields.push((key.data(block).to_vec(), val.data(block).to_vec()))
+            })
+            .expect("DECODE");
+        fields
+    }
+
+    #[test]
+    fn integers() {
+        // RFC 7541, C.1
+        assert_eq!(decode_integer(&[0x0a], 5), Ok((&[][..], 10)));
+        assert_eq!(decode_integer(&[0x1f, 0x9a, 0x0a], 5), Ok((&[][..], 1337)));
+        assert_eq!(decode_integer(&[0x2a], 8), Ok((&[][..], 42)));
+        assert_eq!(
+            decode_integer(&[0x1f, 0x9a], 5),
+            Err(DecoderError::Truncated)
+        );
+        assert_eq!(
+            decode_integer(&[0x1f, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f], 5),
+            Err(DecoderError::IntegerOverflow)
+        );
+    }
+
+    #[test]
+    fn requests_without_huffman() {
+        // RFC 7541, C.3
+        let mut decoder = Decoder::new();
+        let fields = decode_block(&mut decoder, b"\x82\x86\x84\x41\x0fwww.example.com");
+        assert_eq!(
+            fields[3],
+            (b":authority".to_vec(), b"www.example.com".to_vec())
+        );
+        assert_eq!(decoder.table.size(), 57);
+
+        let fields = decode_block(&mut decoder, b"\x82\x86\x84\xbe\x58\x08no-cache");
+        assert_eq!(
+            fields[3],
+            (b":authority".to_vec(), b"www.example.com".to_vec())
+        );
+        assert_eq!(fields[4], (b"cache-control".to_vec(), b"no-cache".to_vec()));
+        assert_eq!(decoder.table.size(), 110);
+
+        let fields = decode_block(
+            &mut decoder,
+            b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value",
+        );
+        assert_eq!(fields[1], (b":scheme".to_vec(), b"https".to_vec()));
+        assert_eq!(fields[2], (b":path".to_vec(), b"/index.html".to_vec()));
+        assert_eq!(
+            fields[3],
+            (b":authority".to_vec(), b"www.example.com".to_vec())
+        );
+        assert_eq!(
+            fields[4],
+            (b"custom-key".to_vec(), b"custom-value".to_vec())
+        );
+        assert_eq!(decoder.table.size(), 164);
+    }
+
+    #[test]
+    fn requests_with_huffman() {
+        // RFC 7541, C.4
+        let mut decoder = Decoder::new();
+        let fields = decode_block(
+            &mut decoder,
+            &[
+                0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab,
+                0x90, 0xf4, 0xff,
+            ],
+        );
+        assert_eq!(fields[0], (b":method".to_vec(), b"GET".to_vec()));
+        assert_eq!(
+            fields[3],
+            (b":authority".to_vec(), b"www.example.com".to_vec())
+        );
+
+        let fields = decode_block(
+            &mut decoder,
+            &[
+                0x82, 0x86, 0x84, 0xbe, 0x58, 0x86, 0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf,
+            ],
+        );
+        assert_eq!(fields[4], (b"cache-control".to_vec(), b"no-cache".to_vec()));
+
+        let fields = decode_block(
+            &mut decoder,
+            &[
+                0x82, 0x87, 0x85, 0xbf, 0x40, 0x88, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xa9, 0x7d, 0x7f,
+                0x89, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xb8, 0xe8, 0xb4, 0xbf,
+            ],
+        );
+        assert_eq!(
+            fields[4],
+            (b"custom-key".to_vec(), b"custom-value".to_vec())
+        );
+        assert_eq!(decoder.table.size(), 164);
+    }
+
+    #[test]
+    fn huffman_encoding() {
+        // RFC 7541, C.4.1 and C.6.1
+        let vectors: [(&[u8], &[u8]); 3] = [
+            (
+                b"www.example.com",
+                &[
+                    0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff,
+                ],
+            ),
+            (b"no-cache", &[0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf]),
+            (
+                b"Mon, 21 Oct 2013 20:13:21 GMT",
+                &[
+                    0xd0, 0x7a, 0xbe, 0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95, 0x04,
+                    0x0b, 0x81, 0x66, 0xe0, 0x82, 0xa6, 0x2d, 0x1b, 0xff,
+                ],
+            ),
+        ];
+        for (raw, encoded) in vectors {
+            let mut out = Vec::new();
+            huffman::encode(raw, &mut out);
+            assert_eq!(out, encoded);
+            assert_eq!(huffman::encoded_len(raw), encoded.len());
+            let mut decoded = Vec::new();
+            huffman::decode(&out, &mut decoded).expect("DECODE");
+            assert_eq!(decoded, raw);
+        }
+        // every symbol round trips
+        let all = (0..=255).collect::<Vec<u8>>();
+        let mut out = Vec::new();
+        huffman::encode(&all, &mut out);
+        let mut decoded = Vec::new();
+        huffman::decode(&out, &mut decoded).expect("DECODE");
+        assert_eq!(decoded, all);
+    }
+
+    #[test]
+    fn encoding() {
+        let mut out = Vec::new();
+        encoder::encode_integer(10, 5, 0, &mut out);
+        encoder::encode_integer(1337, 5, 0, &mut out);
+        encoder::encode_integer(42, 8, 0, &mut out);
+        assert_eq!(out, [0x0a, 0x1f, 0x9a, 0x0a, 0x2a]);
+
+        for huffman in [false, true] {
+            let mut encoder = Encoder::new(huffman);
+            let mut block = Vec::new();
+            encoder.encode(b"Custom-Key", b"custom-value", &mut block);
+            // a Huffman form longer than the raw form is not used
+            encoder.encode(b"x-binary", b"\x00\x01\x02", &mut block);
+            let fields = decode_block(&mut Decoder::new(), &block);
+            assert_eq!(
+                fields,
+                [
+                    (b"custom-key".to_vec(), b"custom-value".to_vec()),
+                    (b"x-binary".to_vec(), b"\x00\x01\x02".to_vec()),
+                ]
+            );
+            assert_eq!(block.len() < 39, huffman);
+        }
+    }
+
+    #[test]
+    fn requests_encoding() {
+        // RFC 7541, C.3 and C.4
+        let requests: [&[(&[u8], &[u8])]; 3] = [
+            &[
+                (b":method", b"GET"),
+                (b":scheme", b"http"),
+                (b":path", b"/"),
+                (b":authority", b"www.example.com"),
+            ],
+            &[
+                (b":method", b"GET"),
+                (b":scheme", b"http"),
+                (b":path", b"/"),
+                (b":authority", b"www.example.com"),
+                (b"cache-control", b"no-cache"),
+            ],
+            &[
+                (b":method", b"GET"),
+                (b":scheme", b"https"),
+                (b":path", b"/index.html"),
+                (b":authority", b"www.example.com"),
+                (b"custom-key", b"custom-value"),
+            ],
+        ];
+        let without_huffman: [&[u8]; 3] = [
+            b"\x82\x86\x84\x41\x0fwww.example.com",
+            b"\x82\x86\x84\xbe\x58\x08no-cache",
+            b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value",
+        ];
+        let with_huffman: [&[u8]; 3] = [
+            &[
+                0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab,
+                0x90, 0xf4, 0xff,
+            ],
+            &[
+                0x82, 0x86, 0x84, 0xbe, 0x58, 0x86, 0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf,
+            ],
+            &[
+                0x82, 0x87, 0x85, 0xbf, 0x40, 0x88, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xa9, 0x7d, 0x7f,
+                0x89, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xb8, 0xe8, 0xb4, 0xbf,
+            ],
+        ];
+        for (huffman, expected) in [(false, without_huffman), (true, with_huffman)] {
+            let mut encoder = Encoder::new(huffman);
+            let mut decoder = Decoder::new();
+            for (fields, expected) in requests.iter().zip(expected) {
+                let mut block = Vec::new();
+                for (name, value) in fields.iter() {
+                    encoder.encode(name, value, &mut block);
+                }
+                assert_eq!(block, expected);
+                let decoded = decode_block(&mut decoder, &block);
+                assert!(decoded
+                    .iter()
+                    .zip(fields.iter())
+                    .all(|((name, value), field)| (&name[..], &value[..]) == *field));
+            }
+            assert_eq!(encoder.table.size(), 164);
+        }
+    }
+
+    #[test]
+    fn invalid_blocks() {
+        let mut decoder = Decoder::new();
+        let block = &[0x80];
+        assert_eq!(
+            decoder.decode(block, block, |_, _| {}),
+            Err(DecoderError::InvalidIndex)
+        );
+        let block = &[0x82, 0x3f, 0xe1, 0x1f];
+        assert_eq!(
+            decoder.decode(block, block, |_, _| {}),
+            Err(DecoderError::InvalidTableSizeUpdate)
+        );
+        // truncated huffman string, padded with a 0 bit
+        let block = &[0x41, 0x81, 0xf0];
+        assert_eq!(
+            decoder.decode(block, block, |_, _| {}),
+            Err(DecoderError::InvalidHuffman)
+        );
+    }
+}
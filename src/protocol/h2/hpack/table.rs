@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+/// Static table of RFC 7541 (Appendix A), the first entry has index 1.
+#[rustfmt::skip]
+pub const STATIC_TABLE: [(&[u8], &[u8]); 61] = [
+    (b":authority", b""),
+    (b":method", b"GET"),
+    (b":method", b"POST"),
+    (b":path", b"/"),
+    (b":path", b"/index.html"),
+    (b":scheme", b"http"),
+    (b":scheme", b"https"),
+    (b":status", b"200"),
+    (b":status", b"204"),
+    (b":status", b"206"),
+    (b":status", b"304"),
+    (b":status", b"400"),
+    (b":status", b"404"),
+    (b":status", b"500"),
+    (b"accept-charset", b""),
+    (b"accept-encoding", b"gzip, deflate"),
+    (b"accept-language", b""),
+    (b"accept-ranges", b""),
+    (b"accept", b""),
+    (b"access-control-allow-origin", b""),
+    (b"age", b""),
+    (b"allow", b""),
+    (b"authorization", b""),
+    (b"cache-control", b""),
+    (b"content-disposition", b""),
+    (b"content-encoding", b""),
+    (b"content-language", b""),
+    (b"content-length", b""),
+    (b"content-location", b""),
+    (b"content-range", b""),
+    (b"content-type", b""),
+    (b"cookie", b""),
+    (b"date", b""),
+    (b"etag", b""),
+    (b"expect", b""),
+    (b"expires", b""),
+    (b"from", b""),
+    (b"host", b""),
+    (b"if-match", b""),
+    (b"if-modified-since", b""),
+    (b"if-none-match", b""),
+    (b"if-range", b""),
+    (b"if-unmodified-since", b""),
+    (b"last-modified", b""),
+    (b"link", b""),
+    (b"location", b""),
+    (b"max-forwards", b""),
+    (b"proxy-authenticate", b""),
+    (b"proxy-authorization", b""),
+    (b"range", b""),
+    (b"referer", b""),
+    (b"refresh", b""),
+    (b"retry-after", b""),
+    (b"server", b""),
+    (b"set-cookie", b""),
+    (b"strict-transport-security", b""),
+    (b"transfer-encoding", b""),
+    (b"user-agent", b""),
+    (b"vary", b""),
+    (b"via", b""),
+    (b"www-authenticate", b""),
+];
+
+/// Find an entry of the static table matching a header field, returns its index (starting at 1)
+/// and if the value matched too. Entries matching both the name and value are preferred.
+pub fn find_static(name: &[u8], value: &[u8]) -> Option<(usize, bool)> {
+    let mut name_match = None;
+    for (index, (entry_name, entry_value)) in STATIC_TABLE.iter().enumerate() {
+        if *entry_name == name {
+            if *entry_value == value {
+                return Some((index + 1, true));
+            }
+            if name_match.is_none() {
+                name_match = Some((index + 1, false));
+            }
+        }
+    }
+    name_match
+}
+
+/// Size overhead accounted for each entry of the dynamic table (RFC 7541, 4.1)
+pub const ENTRY_OVERHEAD: usize = 32;
+
+/// A header field stored in the dynamic table
+pub type Entry = (Box<[u8]>, Box<[u8]>);
+
+/// Dynamic table of RFC 7541 (section 2.3.2). The newest entry is the first one and has the
+/// index following the last entry of the static table.
+#[derive(Debug, Clone, Default)]
+pub struct DynamicTable {
+    entries: VecDeque<Entry>,
+    size: usize,
+    max_size: usize,
+}
+
+impl DynamicTable {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Sum of the sizes of the entries, as defined in RFC 7541 (section 4.1)
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Get an entry by its index in the dynamic table (0 being the newest entry)
+    pub fn get(&self, index: usize) -> Option<(&[u8], &[u8])> {
+        self.entries
+            .get(index)
+            .map(|(name, value)| (&name[..], &value[..]))
+    }
+
+    /// Find an entry matching a header field, returns its index and if the value matched too.
+    /// Entries matching both the name and value are preferred.
+    pub fn find(&self, name: &[u8], value: &[u8]) -> Option<(usize, bool)> {
+        let mut name_match = None;
+        for (index, (entry_name, entry_value)) in self.entries.iter().enumerate() {
+            if &entry_name[..] == name {
+                if &entry_value[..] == value {
+                    return Some((index, true));
+                }
+                if name_match.is_none() {
+                    name_match = Some((index, false));
+                }
+            }
+        }
+        name_match
+    }
+
+    /// Insert a new entry, evicting the oldest entries to make room for it. An entry larger than
+    /// the maximum size empties the table and is not inserted.
+    pub fn insert(&mut self, name: &[u8], value: &[u8]) {
+        let entry_size = name.len() + value.len() + ENTRY_OVERHEAD;
+        if entry_size > self.max_size {
+            self.entries.clear();
+            self.size = 0;
+            return;
+        }
+        self.evict(self.max_size - entry_size);
+        self.size += entry_size;
+        self.entries.push_front((name.into(), value.into()));
+    }
+
+    /// Change the maximum size of the table, evicting the oldest entries if necessary.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict(max_size);
+    }
+
+    fn evict(&mut self, target: usize) {
+        while self.size > target {
+            match self.entries.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+}
@@ -1,3 +1,11 @@
 pub mod converter;
+pub mod frame;
+pub mod grpc;
+pub mod hpack;
+pub mod parser;
 
+pub use crate::protocol::h1::parser::{HashingCallbacks, NoCallbacks, ParserCallbacks};
 pub use converter::H2BlockConverter as BlockConverter;
+pub use frame::{preface, Settings};
+pub use grpc::GrpcBlockConverter;
+pub use parser::{parse, parse_with_frames, FrameCallbacks};
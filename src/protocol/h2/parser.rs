@@ -0,0 +1,525 @@
+use nom::{Err as NomErr, Offset, ParseTo};
+
+use crate::{
+    protocol::{
+        h1::parser::{NoCallbacks, ParserCallbacks},
+        h2::{
+            frame::{
+                frame_header, strip_padding, FrameHeader, FrameType, ACK, END_HEADERS, END_STREAM,
+                FRAME_HEADER_SIZE,
+            },
+            hpack::Decoder,
+        },
+    },
+    storage::{
+        AsBuffer, Block, BodySize, Chunk, ChunkHeader, Flags, Kawa, Kind, Pair, ParsingPhase,
+        StatusLine, Store, Version, EMPTY_CRUMB_NAME,
+    },
+};
+
+#[cfg(feature = "custom-vecdeque")]
+use crate::storage::VecDeque;
+#[cfg(not(feature = "custom-vecdeque"))]
+use std::collections::VecDeque;
+
+/// Callbacks for the frames that don't belong to the stream represented by the Kawa, they are
+/// invoked from parse_with_frames as the frames are parsed.
+pub trait FrameCallbacks {
+    /// Called with the (identifier, value) pairs of a SETTINGS frame, empty for an ACK
+    fn on_settings(&mut self, _settings: &[(u16, u32)], _ack: bool) {}
+    /// Called with the opaque data of a PING frame
+    fn on_ping(&mut self, _data: [u8; 8], _ack: bool) {}
+    /// Called with the window size increment of a WINDOW_UPDATE frame, the stream 0 being the
+    /// connection
+    fn on_window_update(&mut self, _stream_id: u32, _increment: u32) {}
+    /// Called with the last stream id and the error code of a GOAWAY frame
+    fn on_goaway(&mut self, _last_stream_id: u32, _error_code: u32) {}
+}
+
+impl FrameCallbacks for NoCallbacks {}
+
+fn be_u32_at(payload: &[u8], index: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&payload[index..index + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+/// Parse a connection level frame and hand it to the frame callbacks, the other frames are
+/// skipped. Returns false if the frame is invalid.
+fn parse_control<T: AsBuffer, F: FrameCallbacks>(
+    kawa: &mut Kawa<T>,
+    header: &FrameHeader,
+    frame_callbacks: &mut F,
+) -> bool {
+    let head = kawa.storage.head;
+    let payload =
+        &kawa.storage.buffer()[head + FRAME_HEADER_SIZE..head + FRAME_HEADER_SIZE + header.length];
+    let ack = header.flags & ACK != 0;
+    let connection_frame = matches!(
+        header.kind,
+        FrameType::Settings | FrameType::Ping | FrameType::GoAway
+    );
+    let error = if connection_frame && header.stream_id != 0 {
+        Some("Connection frame on a stream")
+    } else {
+        match header.kind {
+            FrameType::Settings if payload.len() % 6 != 0 || ack && !payload.is_empty() => {
+                Some("Invalid SETTINGS frame")
+            }
+            FrameType::Settings => {
+                let settings = payload
+                    .chunks(6)
+                    .map(|setting| {
+                        (
+                            u16::from_be_bytes([setting[0], setting[1]]),
+                            be_u32_at(setting, 2),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                frame_callbacks.on_settings(&settings, ack);
+                None
+            }
+            FrameType::Ping if payload.len() != 8 => Some("Invalid PING frame"),
+            FrameType::Ping => {
+                let mut data = [0; 8];
+                data.copy_from_slice(payload);
+                frame_callbacks.on_ping(data, ack);
+                None
+            }
+            FrameType::WindowUpdate if payload.len() != 4 => Some("Invalid WINDOW_UPDATE frame"),
+            FrameType::WindowUpdate => {
+                let increment = be_u32_at(payload, 0) & 0x7FFF_FFFF;
+                frame_callbacks.on_window_update(header.stream_id, increment);
+                None
+            }
+            FrameType::GoAway if payload.len() < 8 => Some("Invalid GOAWAY frame"),
+            FrameType::GoAway => {
+                let last_stream_id = be_u32_at(payload, 0) & 0x7FFF_FFFF;
+                frame_callbacks.on_goaway(last_stream_id, be_u32_at(payload, 4));
+                None
+            }
+            // PRIORITY, RST_STREAM, PUSH_PROMISE and unknown frames
+            _ => None,
+        }
+    };
+    if let Some(message) = error {
+        kawa.parsing_phase.error(message.into());
+        return false;
+    }
+    kawa.storage.head += FRAME_HEADER_SIZE + header.length;
+    true
+}
+
+/// Create a Store referencing a subslice of the data of another Store, without copying it when
+/// possible.
+fn sub_store(store: &Store, buf: &[u8], data: &[u8]) -> Store {
+    match store {
+        Store::Slice(_) | Store::Detached(_) => Store::new_slice(buf, data),
+        Store::Static(parent) => {
+            let start = (*parent).offset(data);
+            Store::Static(&parent[start..start + data.len()])
+        }
+        _ => Store::from_slice(data),
+    }
+}
+
+/// Split an H2 cookie header in crumbs, a single header may hold several "; " separated crumbs.
+fn push_crumbs(jar: &mut VecDeque<Pair>, buf: &[u8], cookie: &Store) {
+    for crumb in cookie.data(buf).split(|c| *c == b';') {
+        let spaces = crumb.iter().take_while(|c| **c == b' ').count();
+        let crumb = &crumb[spaces..];
+        if crumb.is_empty() {
+            continue;
+        }
+        let (key, val) = match crumb.iter().position(|c| *c == b'=') {
+            Some(0) => (Store::Static(EMPTY_CRUMB_NAME), &crumb[1..]),
+            Some(index) => (sub_store(cookie, buf, &crumb[..index]), &crumb[index + 1..]),
+            // an empty Static key would mark a crumb with an empty name
+            None if cookie.is_static() => (Store::from_slice(&[]), crumb),
+            None => (sub_store(cookie, buf, &crumb[..0]), crumb),
+        };
+        jar.push_back(Pair {
+            key,
+            val: sub_store(cookie, buf, val),
+        });
+    }
+}
+
+/// Gather the header block fragments of a HEADERS frame and its CONTINUATION frames.
+/// The fragments are moved in place so the header block is contiguous in the buffer.
+///
+/// Returns the range of the header block and the end of the last frame, or None if the frames
+/// are not all available yet.
+fn gather_header_block<T: AsBuffer>(
+    kawa: &mut Kawa<T>,
+    header: &FrameHeader,
+) -> Option<(usize, usize, usize)> {
+    let head = kawa.storage.head;
+    let buf = kawa.storage.buffer();
+    let payload = &buf[head + FRAME_HEADER_SIZE..head + FRAME_HEADER_SIZE + header.length];
+    let fragment = match strip_padding(header, payload) {
+        Some(fragment) => fragment,
+        None => {
+            kawa.parsing_phase
+                .error("Invalid HEADERS frame padding".into());
+            return None;
+        }
+    };
+    let block_start = buf.offset(fragment);
+    let mut block_end = block_start + fragment.len();
+    let mut frame_end = head + FRAME_HEADER_SIZE + header.length;
+    let mut end_headers = header.flags & END_HEADERS != 0;
+    let mut fragments = Vec::new();
+    while !end_headers {
+        let next = match frame_header(&buf[frame_end..kawa.storage.end]) {
+            Ok((_, next)) => next,
+            Err(_) => return None,
+        };
+        if next.kind != FrameType::Continuation {
+            kawa.parsing_phase
+                .error("Expected a CONTINUATION frame".into());
+            return None;
+        }
+        if frame_end + FRAME_HEADER_SIZE + next.length > kawa.storage.end {
+            return None;
+        }
+        fragments.push((frame_end + FRAME_HEADER_SIZE, next.length));
+        frame_end += FRAME_HEADER_SIZE + next.length;
+        end_headers = next.flags & END_HEADERS != 0;
+    }
+    let buf = kawa.storage.mut_buffer();
+    for (start, length) in fragments {
+        buf.copy_within(start..start + length, block_end);
+        block_end += length;
+    }
+    Some((block_start, block_end, frame_end))
+}
+
+fn parse_headers<T: AsBuffer, C: ParserCallbacks<T>>(
+    kawa: &mut Kawa<T>,
+    decoder: &mut Decoder,
+    header: &FrameHeader,
+    callbacks: &mut C,
+) -> bool {
+    let (block_start, block_end, frame_end) = match gather_header_block(kawa, header) {
+        Some(block) => block,
+        None => return false,
+    };
+    let end_stream = header.flags & END_STREAM != 0;
+    let trailers = match kawa.parsing_phase {
+        ParsingPhase::StatusLine => false,
+        ParsingPhase::Body | ParsingPhase::Chunks { .. } if end_stream => true,
+        _ => {
+            kawa.parsing_phase.error("Unexpected HEADERS frame".into());
+            return false;
+        }
+    };
+    // trailers can't be represented along a content length body
+    let drop_fields = trailers && kawa.body_size != BodySize::Chunked;
+    if trailers && !drop_fields {
+        kawa.blocks.push_back(Block::Flags(Flags {
+            end_body: true,
+            end_chunk: false,
+            end_header: false,
+            end_stream: false,
+        }));
+    } else if !trailers {
+        kawa.blocks.push_back(Block::StatusLine);
+    }
+
+    let mut method = Store::Empty;
+    let mut authority = Store::Empty;
+    let mut path = Store::Empty;
+    let mut status = Store::Empty;
+    let mut host = Store::Empty;
+    let mut content_length = None;
+    let mut error = None;
+
+    let buf = kawa.storage.buffer();
+    let blocks = &mut kawa.blocks;
+    let jar = &mut kawa.detached.jar;
+    let result = decoder.decode(buf, &buf[block_start..block_end], |key, val| {
+        if drop_fields {
+            return;
+        }
+        let name = key.data(buf);
+        if name.first() == Some(&b':') {
+            if trailers {
+                error = Some("Pseudo header field in trailers");
+                return;
+            }
+            match name {
+                b":method" => method = val,
+                b":authority" => authority = val,
+                b":path" => path = val,
+                b":status" => status = val,
+                b":scheme" | b":protocol" => {}
+                _ => error = Some("Invalid pseudo header field"),
+            }
+        } else if name == b"cookie" && !trailers {
+            if jar.is_empty() {
+                blocks.push_back(Block::Cookies);
+            }
+            push_crumbs(jar, buf, &val);
+        } else if name == b"host" && !trailers {
+            host = val;
+        } else {
+            if name == b"content-length" {
+                match val.data(buf).parse_to() {
+                    Some(length) if content_length.map_or(true, |previous| previous == length) => {
+                        content_length = Some(length)
+                    }
+                    _ => error = Some("Invalid Content-Length field value"),
+                }
+            }
+            blocks.push_back(Block::Header(Pair { key, val }));
+        }
+    });
+    if let Err(decoder_error) = result {
+        kawa.parsing_phase.error(decoder_error.as_str().into());
+        return false;
+    }
+    if let Some(message) = error {
+        kawa.parsing_phase.error(message.into());
+        return false;
+    }
+    kawa.storage.head = frame_end;
+
+    if trailers {
+        kawa.parsing_phase = ParsingPhase::Terminated;
+        kawa.blocks.push_back(Block::Flags(Flags {
+            end_body: drop_fields,
+            end_chunk: false,
+            end_header: !drop_fields,
+            end_stream: true,
+        }));
+        callbacks.on_trailers(kawa);
+        return true;
+    }
+
+    match kawa.kind {
+        Kind::Request => {
+            if method.is_empty() {
+                kawa.parsing_phase
+                    .error("Missing :method pseudo header".into());
+                return false;
+            }
+            // :authority has higher priority than Host header
+            if authority.is_empty() {
+                authority = host;
+            }
+            let uri = if path.is_empty() {
+                authority.clone()
+            } else {
+                path.clone()
+            };
+            kawa.detached.status_line = StatusLine::Request {
+                version: Version::V20,
+                method,
+                authority,
+                path,
+                uri,
+            };
+        }
+        Kind::Response => {
+            let code = match status
+                .data_opt(kawa.storage.buffer())
+                .and_then(|s| s.parse_to())
+            {
+                Some(code) => code,
+                None => {
+                    kawa.parsing_phase
+                        .error("Invalid :status pseudo header".into());
+                    return false;
+                }
+            };
+            kawa.detached.status_line = StatusLine::Response {
+                version: Version::V20,
+                code,
+                status,
+                reason: Store::Static(b""),
+            };
+        }
+    }
+
+    callbacks.on_status_line(kawa);
+
+    kawa.parsing_phase = if end_stream {
+        kawa.body_size = BodySize::Length(0);
+        ParsingPhase::Terminated
+    } else if let Some(length) = content_length {
+        kawa.body_size = BodySize::Length(length);
+        kawa.expects = length;
+        ParsingPhase::Body
+    } else {
+        // the length of the body is unknown, it is represented as a chunked body
+        kawa.body_size = BodySize::Chunked;
+        kawa.blocks.push_back(Block::Header(Pair {
+            key: Store::Static(b"transfer-encoding"),
+            val: Store::Static(b"chunked"),
+        }));
+        ParsingPhase::Chunks { first: false }
+    };
+    callbacks.on_headers(kawa);
+    kawa.blocks.push_back(Block::Flags(Flags {
+        end_body: false,
+        end_chunk: false,
+        end_header: true,
+        end_stream: kawa.is_terminated(),
+    }));
+    true
+}
+
+fn parse_data<T: AsBuffer, C: ParserCallbacks<T>>(
+    kawa: &mut Kawa<T>,
+    header: &FrameHeader,
+    callbacks: &mut C,
+) -> bool {
+    let head = kawa.storage.head;
+    let frame_end = head + FRAME_HEADER_SIZE + header.length;
+    let buf = kawa.storage.buffer();
+    let data = match strip_padding(header, &buf[head + FRAME_HEADER_SIZE..frame_end]) {
+        Some(data) => data,
+        None => {
+            kawa.parsing_phase
+                .error("Invalid DATA frame padding".into());
+            return false;
+        }
+    };
+    let end_stream = header.flags & END_STREAM != 0;
+    match kawa.parsing_phase {
+        ParsingPhase::Body => {
+            if data.len() > kawa.expects || end_stream && data.len() != kawa.expects {
+                kawa.parsing_phase
+                    .error("DATA frames don't match the Content-Length".into());
+                return false;
+            }
+            kawa.expects -= data.len();
+            if !data.is_empty() {
+                callbacks.on_body(kawa, data);
+                kawa.blocks.push_back(Block::Chunk(Chunk {
+                    data: Store::new_slice(buf, data),
+                }));
+            }
+            if end_stream {
+                kawa.blocks.push_back(Block::Flags(Flags {
+                    end_body: true,
+                    end_chunk: false,
+                    end_header: false,
+                    end_stream: true,
+                }));
+            }
+        }
+        ParsingPhase::Chunks { .. } => {
+            if !data.is_empty() {
+                callbacks.on_body(kawa, data);
+                kawa.blocks.push_back(Block::ChunkHeader(ChunkHeader {
+                    length: Store::from_string(format!("{:x}", data.len())),
+                    extensions: Store::Empty,
+                }));
+                kawa.blocks.push_back(Block::Chunk(Chunk {
+                    data: Store::new_slice(buf, data),
+                }));
+                kawa.blocks.push_back(Block::Flags(Flags {
+                    end_body: false,
+                    end_chunk: true,
+                    end_header: false,
+                    end_stream: false,
+                }));
+            }
+            if end_stream {
+                kawa.blocks.push_back(Block::Flags(Flags {
+                    end_body: true,
+                    end_chunk: false,
+                    end_header: false,
+                    end_stream: false,
+                }));
+                kawa.blocks.push_back(Block::Flags(Flags {
+                    end_body: false,
+                    end_chunk: false,
+                    end_header: true,
+                    end_stream: true,
+                }));
+            }
+        }
+        _ => {
+            kawa.parsing_phase.error("Unexpected DATA frame".into());
+            return false;
+        }
+    }
+    if end_stream {
+        kawa.parsing_phase = ParsingPhase::Terminated;
+    }
+    kawa.storage.head = frame_end;
+    true
+}
+
+/// Parse HTTP/2 frames from the unparsed data of the Kawa storage, filling its blocks.
+///
+/// HEADERS and CONTINUATION frames are decoded with the given HPACK decoder, which should be
+/// shared by every stream of a connection. Pseudo headers fill the StatusLine, cookies are split
+/// in crumbs in the jar and DATA frames produce Chunks. A body without content-length is
+/// represented as a chunked body. Frames are only parsed once entirely available, so the buffer
+/// must be able to hold the largest frame (and a header block with all its CONTINUATION frames).
+///
+/// note: frames are expected to belong to the stream represented by this Kawa, the other frames
+/// (PRIORITY, SETTINGS, PING...) are skipped, use parse_with_frames to handle them. The
+/// connection preface is not expected.
+pub fn parse<T: AsBuffer, C: ParserCallbacks<T>>(
+    kawa: &mut Kawa<T>,
+    decoder: &mut Decoder,
+    callbacks: &mut C,
+) {
+    parse_with_frames(kawa, decoder, callbacks, &mut NoCallbacks)
+}
+
+/// Same as parse, but SETTINGS, PING, WINDOW_UPDATE and GOAWAY frames are checked and handed to
+/// the frame callbacks. Other frames, including unknown frame types, are skipped.
+pub fn parse_with_frames<T: AsBuffer, C: ParserCallbacks<T>, F: FrameCallbacks>(
+    kawa: &mut Kawa<T>,
+    decoder: &mut Decoder,
+    callbacks: &mut C,
+    frame_callbacks: &mut F,
+) {
+    loop {
+        if kawa.is_terminated() || kawa.is_error() {
+            return;
+        }
+        let unparsed_buf = kawa.storage.unparsed_data();
+        let header = match frame_header(unparsed_buf) {
+            Ok((_, header)) => header,
+            Err(NomErr::Incomplete(_)) => return,
+            Err(_) => unreachable!(),
+        };
+        if FRAME_HEADER_SIZE + header.length > kawa.storage.capacity() {
+            kawa.parsing_phase
+                .error("Frame larger than the buffer".into());
+            return;
+        }
+        if unparsed_buf.len() < FRAME_HEADER_SIZE + header.length {
+            return;
+        }
+        let parsed = match header.kind {
+            FrameType::Headers | FrameType::Data if header.stream_id == 0 => {
+                kawa.parsing_phase
+                    .error("Stream frame on the connection stream".into());
+                false
+            }
+            FrameType::Headers => parse_headers(kawa, decoder, &header, callbacks),
+            FrameType::Data => parse_data(kawa, &header, callbacks),
+            FrameType::Continuation => {
+                kawa.parsing_phase
+                    .error("Unexpected CONTINUATION frame".into());
+                false
+            }
+            _ => parse_control(kawa, &header, frame_callbacks),
+        };
+        if !parsed {
+            return;
+        }
+        if kawa.is_terminated() {
+            callbacks.on_message_end(kawa);
+            return;
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod h1;
 pub mod h2;
-mod utils;
+pub(crate) mod utils;
+pub mod ws;
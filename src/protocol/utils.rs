@@ -1,11 +1,175 @@
+/// Compare two byte strings ignoring the case of ASCII letters, the blocks of 16 bytes are
+/// compared with simd instructions when enabled.
 pub fn compare_no_case(left: &[u8], right: &[u8]) -> bool {
     if left.len() != right.len() {
         return false;
     }
 
-    left.iter()
-        .zip(right)
-        .all(|(a, b)| *a | 0b00_10_00_00 == *b | 0b00_10_00_00)
+    #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let i = equal_no_case_prefix(left, right);
+    #[cfg(not(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+    let i = 0;
+    left[i..]
+        .iter()
+        .zip(&right[i..])
+        .all(|(a, b)| a.eq_ignore_ascii_case(b))
+}
+
+/// Returns the length of the longest prefix made of blocks of 16 bytes equal in both strings,
+/// ignoring the case of ASCII letters (sse2 optimized)
+///
+/// note: only the bytes between `A` and `Z` are lowercased, the signed comparisons leave the
+/// bytes above 0x7F out of the range.
+#[inline]
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+fn equal_no_case_prefix(left: &[u8], right: &[u8]) -> usize {
+    use std::arch::x86_64::{
+        __m128i, _mm_and_si128, _mm_cmpeq_epi8, _mm_cmpgt_epi8, _mm_cmplt_epi8, _mm_loadu_si128,
+        _mm_movemask_epi8, _mm_or_si128, _mm_set1_epi8,
+    };
+
+    let lowercase = |block: __m128i| unsafe {
+        let upper = _mm_and_si128(
+            _mm_cmpgt_epi8(block, _mm_set1_epi8(b'A' as i8 - 1)),
+            _mm_cmplt_epi8(block, _mm_set1_epi8(b'Z' as i8 + 1)),
+        );
+        _mm_or_si128(block, _mm_and_si128(upper, _mm_set1_epi8(0x20)))
+    };
+    let mut i = 0;
+    while i + 16 <= left.len() {
+        let equal = unsafe {
+            let left_128 = _mm_loadu_si128(left.as_ptr().add(i) as *const __m128i);
+            let right_128 = _mm_loadu_si128(right.as_ptr().add(i) as *const __m128i);
+            _mm_movemask_epi8(_mm_cmpeq_epi8(lowercase(left_128), lowercase(right_128)))
+        };
+        if equal != 0xFFFF {
+            break;
+        }
+        i += 16;
+    }
+    i
+}
+
+/// Returns the length of the longest prefix made of blocks of 16 bytes equal in both strings,
+/// ignoring the case of ASCII letters (neon optimized)
+#[inline]
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+fn equal_no_case_prefix(left: &[u8], right: &[u8]) -> usize {
+    use std::arch::aarch64::{
+        uint8x16_t, vandq_u8, vceqq_u8, vcgeq_u8, vcleq_u8, vdupq_n_u8, vld1q_u8, vminvq_u8,
+        vorrq_u8,
+    };
+
+    let lowercase = |block: uint8x16_t| unsafe {
+        let upper = vandq_u8(
+            vcgeq_u8(block, vdupq_n_u8(b'A')),
+            vcleq_u8(block, vdupq_n_u8(b'Z')),
+        );
+        vorrq_u8(block, vandq_u8(upper, vdupq_n_u8(0x20)))
+    };
+    let mut i = 0;
+    while i + 16 <= left.len() {
+        let equal = unsafe {
+            let left_128 = vld1q_u8(left.as_ptr().add(i));
+            let right_128 = vld1q_u8(right.as_ptr().add(i));
+            vminvq_u8(vceqq_u8(lowercase(left_128), lowercase(right_128)))
+        };
+        if equal != 0xFF {
+            break;
+        }
+        i += 16;
+    }
+    i
+}
+
+/// Remove the leading and trailing spaces and tabs (optional whitespace)
+pub fn trim_ows(data: &[u8]) -> &[u8] {
+    let start = data
+        .iter()
+        .position(|c| *c != b' ' && *c != b'\t')
+        .unwrap_or(data.len());
+    let end = data
+        .iter()
+        .rposition(|c| *c != b' ' && *c != b'\t')
+        .map_or(start, |end| end + 1);
+    &data[start..end]
+}
+
+/// Split an authority in host and port, at the last colon. IPv6 literals keep their brackets
+/// and the colons they contain are not treated as port separators. An empty port is None.
+pub fn split_authority(authority: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let colon = if authority.first() == Some(&b'[') {
+        match authority.iter().position(|c| *c == b']') {
+            Some(end) if authority.get(end + 1) == Some(&b':') => Some(end + 1),
+            _ => None,
+        }
+    } else {
+        authority.iter().rposition(|c| *c == b':')
+    };
+    match colon {
+        Some(colon) if colon + 1 == authority.len() => (&authority[..colon], None),
+        Some(colon) => (&authority[..colon], Some(&authority[colon + 1..])),
+        None => (authority, None),
+    }
+}
+
+/// Compare two authorities, the hosts case insensitively and the ports with `default_port`
+/// standing for a missing port.
+pub fn same_authority(a: &[u8], b: &[u8], default_port: &[u8]) -> bool {
+    let (a_host, a_port) = split_authority(a);
+    let (b_host, b_port) = split_authority(b);
+    compare_no_case(a_host, b_host)
+        && a_port.unwrap_or(default_port) == b_port.unwrap_or(default_port)
+}
+
+/// Returns true if a weight (RFC 9110, 12.4.2) is zero, meaning "not acceptable":
+/// `0` optionally followed by a dot and up to three zeros.
+pub fn is_zero_qvalue(qvalue: &[u8]) -> bool {
+    match qvalue {
+        [b'0'] => true,
+        [b'0', b'.', decimals @ ..] => decimals.len() <= 3 && decimals.iter().all(|c| *c == b'0'),
+        _ => false,
+    }
+}
+
+/// Decode standard base64 (RFC 4648, section 4), the padding is optional.
+/// Returns None on any invalid character or length.
+pub fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    fn sextet(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+    let input = match input.iter().rposition(|c| *c != b'=') {
+        Some(end) if input.len() - end - 1 <= 2 => &input[..=end],
+        Some(_) => return None,
+        None => {
+            return if input.is_empty() {
+                Some(Vec::new())
+            } else {
+                None
+            }
+        }
+    };
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    for group in input.chunks(4) {
+        let mut bits = 0;
+        for c in group {
+            bits = bits << 6 | sextet(*c)?;
+        }
+        bits <<= 6 * (4 - group.len());
+        let bytes = bits.to_be_bytes();
+        output.extend_from_slice(&bytes[1..group.len()]);
+    }
+    Some(output)
 }
 
 #[macro_export]
@@ -118,12 +282,72 @@ macro_rules! compile_lookup {
                 unsafe { *TABLE.get_unchecked(i as usize) }
             }
 
+            #[cfg(all(feature="simd", target_arch="x86_64"))]
+            const BITMAPS: [[u8; 16]; 2] = TABLE.nibble_bitmaps();
+            #[cfg(all(feature="simd", target_arch="x86_64"))]
+            const BITS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+
             #[inline]
-            #[cfg(feature="simd")]
-            /// Returns the longest string that fits the rule (simd optimized)
+            #[cfg(all(feature="simd", target_arch="x86_64"))]
+            /// Returns the length of the longest prefix that fits the rule (avx2 optimized if the
+            /// cpu supports it, sse4.2 optimized otherwise)
+            fn simd_prefix_len(input: &[u8]) -> usize {
+                if input.len() >= 32 && std::is_x86_feature_detected!("avx2") {
+                    unsafe { avx2_prefix_len(input) }
+                } else {
+                    sse42_prefix_len(input)
+                }
+            }
+
+            #[cfg(all(feature="simd", target_arch="x86_64"))]
+            #[target_feature(enable = "avx2")]
+            /// Returns the length of the longest prefix that fits the rule (avx2 optimized)
             ///
-            /// *Streaming version* will return a Err::Incomplete(Needed::Unknown) if the pattern reaches the end of the input.
-            fn take_while_simd(input: &[u8]) -> nom::IResult<&[u8], &[u8]> {
+            /// note: the table is not limited to 8 ranges, each byte is classified with
+            /// _mm256_shuffle_epi8 lookups: its low nibble selects the bitmap of its valid high
+            /// nibbles and its high nibble selects the bit to test. The remaining bytes, less than
+            /// 32, are handled by the sse4.2 version.
+            unsafe fn avx2_prefix_len(input: &[u8]) -> usize {
+                use std::arch::x86_64::{
+                    __m256i, _mm256_and_si256, _mm256_blendv_epi8, _mm256_broadcastsi128_si256,
+                    _mm256_cmpeq_epi8, _mm256_cmpgt_epi8, _mm256_loadu_si256,
+                    _mm256_movemask_epi8, _mm256_set1_epi8, _mm256_shuffle_epi8,
+                    _mm256_srli_epi16, _mm_loadu_si128,
+                };
+
+                let low_bitmaps =
+                    _mm256_broadcastsi128_si256(_mm_loadu_si128(BITMAPS[0].as_ptr() as *const _));
+                let high_bitmaps =
+                    _mm256_broadcastsi128_si256(_mm_loadu_si128(BITMAPS[1].as_ptr() as *const _));
+                let bits = _mm256_broadcastsi128_si256(_mm_loadu_si128(BITS.as_ptr() as *const _));
+                let nibble = _mm256_set1_epi8(0x0F);
+                let seven = _mm256_set1_epi8(7);
+
+                let mut i = 0;
+                while i + 32 <= input.len() {
+                    let input_256 = _mm256_loadu_si256(input.as_ptr().add(i) as *const __m256i);
+                    let low = _mm256_and_si256(input_256, nibble);
+                    let high = _mm256_and_si256(_mm256_srli_epi16(input_256, 4), nibble);
+                    let bitmap = _mm256_blendv_epi8(
+                        _mm256_shuffle_epi8(low_bitmaps, low),
+                        _mm256_shuffle_epi8(high_bitmaps, low),
+                        _mm256_cmpgt_epi8(high, seven),
+                    );
+                    let bit = _mm256_shuffle_epi8(bits, high);
+                    let valid = _mm256_cmpeq_epi8(_mm256_and_si256(bitmap, bit), bit);
+                    let mask = _mm256_movemask_epi8(valid) as u32;
+                    if mask != u32::MAX {
+                        return i + (!mask).trailing_zeros() as usize;
+                    }
+                    i += 32;
+                }
+                i + sse42_prefix_len(input.get_unchecked(i..))
+            }
+
+            #[inline]
+            #[cfg(all(feature="simd", target_arch="x86_64"))]
+            /// Returns the length of the longest prefix that fits the rule (sse4.2 optimized)
+            fn sse42_prefix_len(input: &[u8]) -> usize {
                 use std::arch::x86_64::{
                     _mm_cmpestri, _mm_lddqu_si128, _mm_loadu_si128, _SIDD_CMP_RANGES,
                     _SIDD_LEAST_SIGNIFICANT, _SIDD_UBYTE_OPS,
@@ -131,8 +355,7 @@ macro_rules! compile_lookup {
 
                 let start = input.as_ptr() as usize;
                 let mut i = input.as_ptr() as usize;
-                let limit = input.as_ptr() as usize + input.len() - 16;
-                let mut found = false;
+                let limit = start + input.len().saturating_sub(16);
 
                 while i < limit {
                     let ranges_128 = unsafe { _mm_loadu_si128(RANGES.as_ptr() as *const _) };
@@ -147,23 +370,70 @@ macro_rules! compile_lookup {
                         )
                     };
                     if index != 16 {
-                        i += index as usize;
-                        found = true;
-                        break;
+                        return i - start + index as usize;
                     }
                     i += 16;
                 }
 
                 let mut i = i - start;
-                if !found {
-                    while i < input.len() {
-                        if unsafe { !TABLE.get_unchecked(*input.get_unchecked(i) as usize) } {
-                            break;
+                while i < input.len() {
+                    if unsafe { !TABLE.get_unchecked(*input.get_unchecked(i) as usize) } {
+                        break;
+                    }
+                    i += 1;
+                }
+                i
+            }
+
+            #[inline]
+            #[cfg(all(feature="simd", target_arch="aarch64"))]
+            /// Returns the length of the longest prefix that fits the rule (neon optimized)
+            ///
+            /// note: each block of 16 bytes is compared to every range, a block containing an
+            /// invalid character is then scanned with the table to find its position.
+            fn simd_prefix_len(input: &[u8]) -> usize {
+                use std::arch::aarch64::{
+                    vandq_u8, vcgeq_u8, vcleq_u8, vdupq_n_u8, vld1q_u8, vmaxvq_u8, vorrq_u8,
+                };
+
+                let mut i = 0;
+                while i + 16 <= input.len() {
+                    let invalid = unsafe {
+                        let input_128 = vld1q_u8(input.as_ptr().add(i));
+                        let mut invalid = vdupq_n_u8(0);
+                        let mut range = 0;
+                        while range < LENGTH as usize {
+                            let low = vdupq_n_u8(RANGES[range]);
+                            let high = vdupq_n_u8(RANGES[range + 1]);
+                            let in_range =
+                                vandq_u8(vcgeq_u8(input_128, low), vcleq_u8(input_128, high));
+                            invalid = vorrq_u8(invalid, in_range);
+                            range += 2;
                         }
-                        i += 1;
+                        vmaxvq_u8(invalid)
+                    };
+                    if invalid != 0 {
+                        break;
                     }
+                    i += 16;
                 }
 
+                while i < input.len() {
+                    if unsafe { !TABLE.get_unchecked(*input.get_unchecked(i) as usize) } {
+                        break;
+                    }
+                    i += 1;
+                }
+                i
+            }
+
+            #[inline]
+            #[cfg(all(feature="simd", any(target_arch="x86_64", target_arch="aarch64")))]
+            /// Returns the longest string that fits the rule (simd optimized)
+            ///
+            /// *Streaming version* will return a Err::Incomplete(Needed::Unknown) if the pattern reaches the end of the input.
+            fn take_while_simd(input: &[u8]) -> nom::IResult<&[u8], &[u8]> {
+                let i = simd_prefix_len(input);
                 if i == input.len() {
                     return Err(nom::Err::Incomplete(nom::Needed::Unknown));
                 } else {
@@ -177,49 +447,10 @@ macro_rules! compile_lookup {
             }
 
             #[inline]
-            #[cfg(feature="simd")]
+            #[cfg(all(feature="simd", any(target_arch="x86_64", target_arch="aarch64")))]
             /// Returns the longest string that fits the rule (simd optimized)
             fn take_while_complete_simd(input: &[u8]) -> nom::IResult<&[u8], &[u8]> {
-                use std::arch::x86_64::{
-                    _mm_cmpestri, _mm_lddqu_si128, _mm_loadu_si128, _SIDD_CMP_RANGES,
-                    _SIDD_LEAST_SIGNIFICANT, _SIDD_UBYTE_OPS,
-                };
-
-                let start = input.as_ptr() as usize;
-                let mut i = input.as_ptr() as usize;
-                let limit = input.as_ptr() as usize + input.len() - 16;
-                let mut found = false;
-
-                while i < limit {
-                    let ranges_128 = unsafe { _mm_loadu_si128(RANGES.as_ptr() as *const _) };
-                    let input_128 = unsafe { _mm_lddqu_si128(i as *const _) };
-                    let index = unsafe {
-                        _mm_cmpestri(
-                            ranges_128,
-                            LENGTH,
-                            input_128,
-                            16,
-                            _SIDD_LEAST_SIGNIFICANT | _SIDD_CMP_RANGES | _SIDD_UBYTE_OPS,
-                        )
-                    };
-                    if index != 16 {
-                        i += index as usize;
-                        found = true;
-                        break;
-                    }
-                    i += 16;
-                }
-
-                let mut i = i - start;
-                if !found {
-                    while i < input.len() {
-                        if unsafe { !TABLE.get_unchecked(*input.get_unchecked(i) as usize) } {
-                            break;
-                        }
-                        i += 1;
-                    }
-                }
-
+                let i = simd_prefix_len(input);
                 unsafe {
                     Ok((
                         input.get_unchecked(i..),
@@ -278,9 +509,9 @@ macro_rules! compile_lookup {
             ///
             /// *Streaming version* will return a Err::Incomplete(Needed::Unknown) if the pattern reaches the end of the input.
             pub fn take_while_fast(input: &[u8]) -> nom::IResult<&[u8], &[u8]> {
-                #[cfg(feature="simd")]
+                #[cfg(all(feature="simd", any(target_arch="x86_64", target_arch="aarch64")))]
                 let result = take_while_simd(input);
-                #[cfg(not(feature="simd"))]
+                #[cfg(not(all(feature="simd", any(target_arch="x86_64", target_arch="aarch64"))))]
                 let result = take_while(input);
                 result
             }
@@ -289,9 +520,9 @@ macro_rules! compile_lookup {
             #[allow(dead_code)]
             /// Returns the longest string that fits the rule (using simd if enabled)
             pub fn take_while_complete_fast(input: &[u8]) -> nom::IResult<&[u8], &[u8]> {
-                #[cfg(feature="simd")]
+                #[cfg(all(feature="simd", any(target_arch="x86_64", target_arch="aarch64")))]
                 let result = take_while_complete_simd(input);
-                #[cfg(not(feature="simd"))]
+                #[cfg(not(all(feature="simd", any(target_arch="x86_64", target_arch="aarch64"))))]
                 let result = take_while_complete(input);
                 result
             }
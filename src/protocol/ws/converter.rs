@@ -0,0 +1,48 @@
+use crate::storage::{AsBuffer, Block, BlockConverter, Chunk, Kawa, Store};
+
+/// FIN bit and binary opcode
+pub const BINARY_FRAME: u8 = 0x82;
+
+/// WebSocket converter for the body of an upgraded connection, server to client.
+///
+/// Each Chunk is wrapped in a single unmasked binary frame. Only the frame header is copied,
+/// the payload is pushed as is. The other blocks belong to the HTTP handshake and are dropped,
+/// they should be converted by an H1BlockConverter before the upgrade.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WsBlockConverter;
+
+/// Returns the header of an unmasked frame, the length uses the shortest encoding:
+/// 7 bits, 16 bits after a 126 marker or 64 bits after a 127 marker
+pub fn frame_header(opcode: u8, length: usize) -> ([u8; 10], usize) {
+    let mut header = [0; 10];
+    header[0] = opcode;
+    let size = if length < 126 {
+        header[1] = length as u8;
+        2
+    } else if length <= u16::MAX as usize {
+        header[1] = 126;
+        header[2..4].copy_from_slice(&(length as u16).to_be_bytes());
+        4
+    } else {
+        header[1] = 127;
+        header[2..10].copy_from_slice(&(length as u64).to_be_bytes());
+        10
+    };
+    (header, size)
+}
+
+impl<T: AsBuffer> BlockConverter<T> for WsBlockConverter {
+    fn call(&mut self, block: Block, kawa: &mut Kawa<T>) -> bool {
+        // the other blocks are not part of the WebSocket stream
+        if let Block::Chunk(Chunk { data }) = block {
+            // an empty Chunk would make an empty frame, it is skipped
+            let length = data.len();
+            if length > 0 {
+                let (header, size) = frame_header(BINARY_FRAME, length);
+                kawa.push_out(Store::from_slice(&header[..size]));
+                kawa.push_out(data);
+            }
+        }
+        true
+    }
+}
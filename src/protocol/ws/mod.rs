@@ -0,0 +1,4 @@
+pub mod converter;
+
+pub use converter::WsBlockConverter as BlockConverter;
+pub use converter::WsBlockConverter;
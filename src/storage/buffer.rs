@@ -1,4 +1,7 @@
-use std::{cmp::min, io, ptr};
+use std::{
+    cmp::{max, min},
+    io, ptr,
+};
 
 /// AsBuffer is the trait used by Buffer to oparate on an arbitrary buffer.
 /// This is to allow the user to use Kawa over any type as long as it exposes a continious slice
@@ -9,6 +12,28 @@ pub trait AsBuffer {
     fn as_mut_buffer(&mut self) -> &mut [u8];
 }
 
+/// GrowableBuffer is implemented by AsBuffer types that can extend their length.
+/// Growing must keep the existing bytes in place and only append new ones at the end, so that
+/// offsets into the buffer (such as the ones held by Store::Slice) remain valid.
+pub trait GrowableBuffer: AsBuffer {
+    fn grow(&mut self, new_len: usize);
+}
+
+impl AsBuffer for Vec<u8> {
+    fn as_buffer(&self) -> &[u8] {
+        self
+    }
+    fn as_mut_buffer(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl GrowableBuffer for Vec<u8> {
+    fn grow(&mut self, new_len: usize) {
+        self.resize(new_len, 0);
+    }
+}
+
 /// Buffer is a pseudo ring buffer specifically designed to store data being parsed
 /// ```txt
 /// buffer        start   half     head  end   len
@@ -63,6 +88,9 @@ impl<T: AsBuffer + Clone> Clone for Buffer<T> {
 }
 
 impl<T: AsBuffer> Buffer<T> {
+    /// The buffer must at least be large enough to hold the status line and the longest header
+    /// line, otherwise the parser can never make progress. A zero length buffer doesn't panic but
+    /// can't hold any data.
     pub fn new(buffer: T) -> Self {
         Self {
             start: 0,
@@ -75,6 +103,9 @@ impl<T: AsBuffer> Buffer<T> {
     pub fn meter(&self, half: usize) -> String {
         let size = half * 2 + 1;
         let len = self.capacity();
+        if len == 0 {
+            return String::from("[]");
+        }
         (0..size + 2)
             .map(|i| {
                 if i == 0 {
@@ -122,9 +153,12 @@ impl<T: AsBuffer> Buffer<T> {
         count
     }
 
+    /// Discard `count` bytes from the start of the data. Consuming unparsed data discards it: `head`
+    /// is moved forward so that it never falls behind `start`.
     pub fn consume(&mut self, count: usize) -> usize {
         let count = min(count, self.available_data());
         self.start += count;
+        self.head = max(self.head, self.start);
         count
     }
 
@@ -169,6 +203,7 @@ impl<T: AsBuffer> Buffer<T> {
     pub fn shift(&mut self) -> usize {
         let start = self.start;
         let end = self.end;
+        debug_assert!(start <= self.head && self.head <= end);
         if start > 0 {
             unsafe {
                 let len = end - start;
@@ -186,6 +221,22 @@ impl<T: AsBuffer> Buffer<T> {
     }
 }
 
+impl<T: GrowableBuffer> Buffer<T> {
+    /// Make sure at least `additional` bytes can be written after `end`, growing the underlying
+    /// buffer if necessary. The capacity is at least doubled to amortize successive growths.
+    ///
+    /// Growth only appends space at the end of the buffer: `start`, `head` and `end` are left
+    /// untouched and every existing Store::Slice still resolves to the same bytes. It should be
+    /// called between two parsing passes, before new data is written and new Slices are created.
+    pub fn ensure_space(&mut self, additional: usize) {
+        if self.available_space() >= additional {
+            return;
+        }
+        let new_len = max(self.end + additional, self.capacity() * 2);
+        self.buffer.grow(new_len);
+    }
+}
+
 impl<T: AsBuffer> io::Write for Buffer<T> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match self.space().write(buf) {
@@ -147,6 +147,8 @@ impl ChunkHeader {
         result.write_fmt(format_args!("ChunkHeader {{"))?;
         result.write_fmt(format_args!("\n{pad}  length: "))?;
         self.length.debug(buf, &pad_field, result)?;
+        result.write_fmt(format_args!(",\n{pad}  extensions: "))?;
+        self.extensions.debug(buf, &pad_field, result)?;
         result.write_fmt(format_args!(",\n{pad}}}"))?;
         Ok(())
     }
@@ -216,7 +218,7 @@ impl Store {
                     to_utf8(Some(&data[*index as usize..]))
                 ))?;
             }
-            #[cfg(feature = "rc-alloc")]
+            #[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
             Store::Shared(data, index) => {
                 result.write_fmt(format_args!(
                     "Store::Shared({:?}, {:?})",
@@ -224,6 +226,14 @@ impl Store {
                     to_utf8(Some(&data[*index as usize..]))
                 ))?;
             }
+            #[cfg(feature = "arc-alloc")]
+            Store::SharedArc(data, index) => {
+                result.write_fmt(format_args!(
+                    "Store::SharedArc({:?}, {:?})",
+                    to_utf8(Some(&data[..*index as usize])),
+                    to_utf8(Some(&data[*index as usize..]))
+                ))?;
+            }
         }
         Ok(())
     }
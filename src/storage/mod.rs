@@ -3,16 +3,20 @@ pub mod debug;
 pub mod repr;
 pub mod vecdeque;
 
-pub use buffer::{AsBuffer, Buffer};
+pub use buffer::{AsBuffer, Buffer, GrowableBuffer};
 pub use debug::debug_kawa;
 pub use repr::{
-    Block, BodySize, Chunk, ChunkHeader, Flags, Kawa, Kind, OutBlock, Pair, ParsingErrorKind,
-    ParsingPhase, ParsingPhaseMarker, StatusLine, Store, Version,
+    Block, BodySize, Chunk, ChunkHeader, ErrorContext, Flags, Kawa, KeepAliveParams, Kind,
+    OutBlock, Pair, ParserState, ParsingError, ParsingErrorKind, ParsingPhase, ParsingPhaseMarker,
+    PrepareOutcome, Role, StatusLine, Store, Version, DEFAULT_MAX_HEADERS, DEFAULT_MAX_HEADER_LINE,
+    EMPTY_CRUMB_NAME,
 };
 pub use vecdeque::VecDeque;
 
 pub trait BlockConverter<T: AsBuffer> {
     fn initialize(&mut self, _kawa: &mut Kawa<T>) {}
+    /// Convert a block, returning false stops the conversion. A block that was not converted
+    /// must be handed back with Kawa::push_block_front or it is lost.
     fn call(&mut self, block: Block, kawa: &mut Kawa<T>) -> bool;
     fn finalize(&mut self, _kawa: &mut Kawa<T>) {}
 }
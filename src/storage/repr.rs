@@ -1,8 +1,13 @@
-use std::io::IoSlice;
-#[cfg(feature = "rc-alloc")]
+#[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
 use std::rc::Rc;
+#[cfg(feature = "arc-alloc")]
+use std::sync::Arc;
+use std::{cmp::min, fmt, io::IoSlice};
 
-use crate::storage::{AsBuffer, BlockConverter, Buffer};
+use crate::{
+    protocol::utils::{compare_no_case, decode_base64, is_zero_qvalue, split_authority, trim_ows},
+    storage::{AsBuffer, BlockConverter, Buffer},
+};
 
 #[cfg(feature = "custom-vecdeque")]
 use crate::storage::VecDeque;
@@ -30,11 +35,74 @@ pub struct Kawa<T: AsBuffer> {
     pub parsing_phase: ParsingPhase,
     pub body_size: BodySize,
 
+    /// Value of the Upgrade header when the message upgrades the connection: a request with an
+    /// "upgrade" Connection option or a 101 response. The body is then parsed as an opaque
+    /// tunnel read until the connection closes. The value is copied, it outlives the Buffer.
+    pub upgrade: Option<Store>,
+    /// Whether the connection becomes a tunnel after the message: a CONNECT request or a 2xx
+    /// response to a CONNECT request. The message has no body and is terminated after its headers,
+    /// the bytes following it are left unparsed and should be forwarded as is.
+    pub tunnel: bool,
+    /// Body framing declared by the headers of a response that can't have a body (1xx, 204 and
+    /// 304), it is ignored and body_size is Length(0). A non-zero Content-Length or a chunked
+    /// Transfer-Encoding there can be a smuggling attempt: whatever follows the headers is parsed
+    /// as the next message.
+    pub ignored_framing: Option<BodySize>,
+    /// Offsets in the buffer of the start of the status line and of the end of the headers, set
+    /// by the H1 parser. The end equals the start until the headers are entirely parsed.
+    pub(crate) header_region: Option<(usize, usize)>,
+
     /// The "consumed" field is not directly used by Kawa, it is intended for proxies, mainly to
     /// easily know if a request started to be transfered. Kawa is responsible for setting it.
     pub consumed: bool,
+
+    /// Maximum number of headers accepted by the parsers, trailers and each crumb of the cookie
+    /// headers included, once exceeded the parsing fails with ParsingErrorKind::TooManyHeaders.
+    pub max_headers: usize,
+    /// Number of headers parsed so far, it is reset by clear.
+    pub(crate) header_count: usize,
+    /// Maximum length of a header line (CRLF excluded) accepted by the parsers, once exceeded
+    /// the parsing fails with ParsingErrorKind::HeaderLineTooLong. Unlike the other length limits
+    /// it defaults to DEFAULT_MAX_HEADER_LINE, so longer lines that used to be accepted are now
+    /// rejected, set it to usize::MAX to disable it.
+    pub max_header_line: usize,
+    /// Maximum length of the request target accepted by the H1 parser, once exceeded the parsing
+    /// fails with ParsingErrorKind::UriTooLong. Unlimited by default.
+    pub max_uri_length: usize,
+    /// Maximum size of the status line and headers (line endings and the empty line ending the
+    /// section included) accepted by the H1 parser, once exceeded the parsing fails with
+    /// ParsingErrorKind::HeaderSectionTooLarge. Unlimited by default.
+    pub max_header_section: usize,
+    /// Whether the requests are received by a proxy or by an origin server, see Role.
+    pub role: Role,
+    /// Skip the header lines that can't be parsed instead of failing, for legacy clients sending
+    /// garbage lines. Skipped lines are reported with ParserCallbacks::on_invalid_header.
+    pub skip_invalid_headers: bool,
+    /// Reject requests whose Host header disagrees with the authority of the request target
+    /// (compared case insensitively, with the default port of the scheme), instead of silently
+    /// ignoring the Host header.
+    pub strict_host: bool,
+    /// For responses: whether the request was a HEAD, the response then has no body whatever
+    /// its framing headers. It must be set before parsing each response and is reset by clear.
+    pub head_request: bool,
+    /// For responses: whether the request was a CONNECT, a 2xx response then opens a tunnel. It
+    /// must be set before parsing each response and is reset by clear.
+    pub connect_request: bool,
+    /// Length of the current header line already parsed, it is used when a line is parsed in
+    /// several steps (cookie crumbs).
+    pub(crate) line_length: usize,
+    /// Number of bytes of the current line, from the head of the buffer, already scanned by the
+    /// H1 parser without finding its end. They are not scanned again when more data is received.
+    pub(crate) line_scanned: usize,
 }
 
+/// Default value of Kawa::max_headers
+pub const DEFAULT_MAX_HEADERS: usize = 128;
+/// Default value of Kawa::max_header_line
+pub const DEFAULT_MAX_HEADER_LINE: usize = 8192;
+/// Key of the cookie crumbs with an empty name ("=value"), see Pair::has_crumb_separator
+pub const EMPTY_CRUMB_NAME: &[u8] = b"";
+
 impl<T: AsBuffer> Kawa<T> {
     /// Create a new Kawa struct around a given storage.
     ///
@@ -48,12 +116,28 @@ impl<T: AsBuffer> Kawa<T> {
             expects: 0,
             parsing_phase: ParsingPhase::StatusLine,
             body_size: BodySize::Empty,
+            upgrade: None,
+            tunnel: false,
+            ignored_framing: None,
+            header_region: None,
             storage,
             detached: DetachedBlocks {
                 status_line: StatusLine::Unknown,
                 jar: VecDeque::new(),
             },
             consumed: false,
+            max_headers: DEFAULT_MAX_HEADERS,
+            header_count: 0,
+            max_header_line: DEFAULT_MAX_HEADER_LINE,
+            max_uri_length: usize::MAX,
+            max_header_section: usize::MAX,
+            role: Role::Proxy,
+            strict_host: false,
+            skip_invalid_headers: false,
+            head_request: false,
+            connect_request: false,
+            line_length: 0,
+            line_scanned: 0,
         }
     }
 
@@ -63,6 +147,11 @@ impl<T: AsBuffer> Kawa<T> {
         for block in &mut self.out {
             block.push_left(amount);
         }
+        let amount = amount as usize;
+        self.header_region = match self.header_region {
+            Some((start, end)) if start >= amount => Some((start - amount, end - amount)),
+            _ => None,
+        };
     }
 
     /// Convert Kawa representation from Blocks to a protocol specific representation in out.
@@ -75,14 +164,24 @@ impl<T: AsBuffer> Kawa<T> {
     ///
     /// note 2: converters can push delimiters in the out vector (via push_delimiter) to fragment
     /// the "stream". This can be used to split H2 frames.
-    pub fn prepare<C: BlockConverter<T>>(&mut self, converter: &mut C) {
+    ///
+    /// note 3: the conversion stops as soon as BlockConverter::call returns false. The block given
+    /// to that call belongs to the converter: if it was not converted, the converter must hand it
+    /// back with push_block_front, so it is the first block converted on the next prepare call.
+    ///
+    /// Returns how many blocks were converted and whether the converter paused the conversion.
+    pub fn prepare<C: BlockConverter<T>>(&mut self, converter: &mut C) -> PrepareOutcome {
+        let mut outcome = PrepareOutcome::default();
         converter.initialize(self);
         while let Some(block) = self.blocks.pop_front() {
             if !converter.call(block, self) {
+                outcome.paused = true;
                 break;
             }
+            outcome.converted += 1;
         }
         converter.finalize(self);
+        outcome
     }
 
     /// Return a vector of IoSlices collecting every bytes from the out vector up to its end or a
@@ -104,6 +203,29 @@ impl<T: AsBuffer> Kawa<T> {
             .collect()
     }
 
+    /// Copy the pending output, up to the next delimiter, in `dst` and consume what was copied.
+    /// This is the non vectored counterpart of as_io_slice followed by consume, for I/O models
+    /// writing from a fixed buffer. Returns the number of bytes copied, 0 if out is empty or
+    /// starts with a delimiter (which is then consumed).
+    pub fn read_out(&mut self, dst: &mut [u8]) -> usize {
+        let buf = self.storage.buffer();
+        let mut copied = 0;
+        for block in &self.out {
+            let data = match block {
+                OutBlock::Delimiter => break,
+                OutBlock::Store(store) => store.data(buf),
+            };
+            let len = min(data.len(), dst.len() - copied);
+            dst[copied..copied + len].copy_from_slice(&data[..len]);
+            copied += len;
+            if copied == dst.len() {
+                break;
+            }
+        }
+        self.consume(copied);
+        copied
+    }
+
     /// Given an amount of bytes consumed, this method removes the relevant OutBlocks from the out
     /// vector and truncates any partially consumed block. It manages the underlying Buffer,
     /// shifting and synchronizing the data if it deems appropriate.
@@ -126,7 +248,7 @@ impl<T: AsBuffer> Kawa<T> {
         }
         assert!(amount == 0);
 
-        let can_consume = self.leftmost_ref() - self.storage.start;
+        let can_consume = self.leftmost_ref().saturating_sub(self.storage.start);
         self.storage.consume(can_consume);
 
         if self.storage.should_shift() {
@@ -135,13 +257,52 @@ impl<T: AsBuffer> Kawa<T> {
         }
     }
 
+    /// Move the live bytes of the Buffer (from the leftmost referenced byte to the end) at the
+    /// start of a new underlying buffer, rebase all the Stores referencing them, and return the
+    /// previous underlying buffer. This allows releasing a large buffer held by a small message.
+    /// Returns None, leaving the Kawa untouched, if the live bytes don't fit in the new buffer.
+    pub fn rebase_onto(&mut self, mut new: T) -> Option<T> {
+        let detached = self.detached.status_line.stores().into_iter().chain(
+            self.detached
+                .jar
+                .iter()
+                .flat_map(|pair| [&pair.key, &pair.val]),
+        );
+        let start = detached
+            .filter_map(Store::slice_start)
+            .fold(self.leftmost_ref(), usize::min);
+        let end = self.storage.end;
+        let len = end - start;
+        if new.as_buffer().len() < len {
+            return None;
+        }
+        new.as_mut_buffer()[..len].copy_from_slice(&self.storage.buffer()[start..end]);
+        let old = std::mem::replace(&mut self.storage.buffer, new);
+        self.storage.start = self.storage.start.saturating_sub(start);
+        self.storage.head -= start;
+        self.storage.end = len;
+
+        let amount = start as u32;
+        self.push_left(amount);
+        for block in &mut self.blocks {
+            block.push_left(amount);
+        }
+        self.detached.status_line.push_left(amount);
+        for pair in &mut self.detached.jar {
+            pair.key.push_left(amount);
+            pair.val.push_left(amount);
+        }
+        Some(old)
+    }
+
     /// Returns how much leading bytes from the Buffer are useless, meaning not referenced by
     /// any Store. It measures how much memory could be saved by shifting the Buffer. It can
     /// be used for monitoring, but it's intended use is internal only.
     pub fn leftmost_ref(&self) -> usize {
         for store in &self.out {
             if let OutBlock::Store(Store::Slice(slice)) = store {
-                return slice.start as usize;
+                // never ahead of head, consuming up to it must not discard unparsed data
+                return (slice.start as usize).min(self.storage.head);
             }
         }
         if self.blocks.is_empty() {
@@ -155,6 +316,48 @@ impl<T: AsBuffer> Kawa<T> {
     pub fn push_block(&mut self, block: Block) {
         self.blocks.push_back(block)
     }
+    /// Put a block back at the front of the blocks, it is meant for converters that stop the
+    /// conversion before converting the block they were given.
+    pub fn push_block_front(&mut self, block: Block) {
+        self.blocks.push_front(block)
+    }
+    /// Start a synthetic response (e.g. an error page or a redirect): set its status line and
+    /// push the StatusLine block. The headers can then be added with push_header, and the message
+    /// closed with push_end.
+    pub fn push_status_line_response(
+        &mut self,
+        code: u16,
+        reason: &'static [u8],
+        version: Version,
+    ) {
+        self.detached.status_line = StatusLine::Response {
+            version,
+            code,
+            status: Store::from_string(code.to_string()),
+            reason: Store::Static(reason),
+        };
+        self.blocks.push_back(Block::StatusLine);
+        self.parsing_phase = ParsingPhase::Headers;
+    }
+    /// Push the Flags block ending the headers of a synthetic message, or ending its body if the
+    /// headers are already ended. With `end_stream` the message is terminated.
+    pub fn push_end(&mut self, end_stream: bool) {
+        let end_header = matches!(
+            self.parsing_phase,
+            ParsingPhase::StatusLine | ParsingPhase::Headers
+        );
+        self.blocks.push_back(Block::Flags(Flags {
+            end_body: !end_header,
+            end_chunk: false,
+            end_header,
+            end_stream,
+        }));
+        self.parsing_phase = if end_stream {
+            ParsingPhase::Terminated
+        } else {
+            ParsingPhase::Body
+        };
+    }
     pub fn push_out(&mut self, store: Store) {
         self.out.push_back(OutBlock::Store(store))
     }
@@ -162,6 +365,450 @@ impl<T: AsBuffer> Kawa<T> {
         self.out.push_back(OutBlock::Delimiter)
     }
 
+    /// Returns the host, port and path of a request. The authority comes from the request line
+    /// or from the Host header. Returns None for responses, if the authority is missing or if
+    /// the port is not a valid number.
+    pub fn request_uri_parts(&self) -> Option<(&[u8], Option<u16>, &[u8])> {
+        let buf = self.storage.buffer();
+        let (authority, path) = match &self.detached.status_line {
+            StatusLine::Request {
+                authority, path, ..
+            } => (
+                authority.data_opt(buf)?,
+                path.data_opt(buf).unwrap_or_default(),
+            ),
+            _ => return None,
+        };
+        let (host, port) = split_authority(authority);
+        let port = match port {
+            Some(port) if port.iter().all(u8::is_ascii_digit) => {
+                Some(std::str::from_utf8(port).ok()?.parse().ok()?)
+            }
+            Some(_) => return None,
+            None => None,
+        };
+        Some((host, port, path))
+    }
+
+    /// Returns the value of the first header with the given name (case insensitive), elided
+    /// headers are skipped.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn find_header(&self, name: &[u8]) -> Option<&[u8]> {
+        let buf = self.storage.buffer();
+        self.blocks.iter().find_map(|block| match block {
+            Block::Header(Pair { key, val }) if key.equals_no_case(buf, name) => {
+                Some(val.data(buf))
+            }
+            _ => None,
+        })
+    }
+
+    /// Iterate over the values of all the headers with the given name (case insensitive), in
+    /// order, elided headers are skipped.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn header_values<'a>(&'a self, name: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+        let buf = self.storage.buffer();
+        self.blocks.iter().filter_map(move |block| match block {
+            Block::Header(Pair { key, val }) if key.equals_no_case(buf, name) => {
+                Some(val.data(buf))
+            }
+            _ => None,
+        })
+    }
+
+    /// Iterate over the data of the Chunk blocks, in order. Only the body parsed and not yet
+    /// converted is available, chunk headers and trailers are not included.
+    pub fn body_chunks(&self) -> impl Iterator<Item = &[u8]> {
+        let buf = self.storage.buffer();
+        self.blocks.iter().filter_map(move |block| match block {
+            Block::Chunk(Chunk { data }) => Some(data.data(buf)),
+            _ => None,
+        })
+    }
+
+    /// Elide every header with the given name (case insensitive), returns how many were elided.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn remove_header(&mut self, name: &[u8]) -> usize {
+        let buf = self.storage.buffer();
+        let mut removed = 0;
+        for block in &mut self.blocks {
+            match block {
+                Block::Header(header) if header.key.equals_no_case(buf, name) => {
+                    header.elide();
+                    removed += 1;
+                }
+                _ => {}
+            }
+        }
+        removed
+    }
+
+    /// Add a header at the end of the header section, before the Flags marking its end. If the
+    /// header section is not fully parsed yet, the header is appended to the blocks.
+    ///
+    /// note: the header is inserted in the blocks, so this must be called before prepare.
+    pub fn push_header(&mut self, key: Store, val: Store) {
+        let position = self
+            .blocks
+            .iter()
+            .position(|block| {
+                matches!(
+                    block,
+                    Block::Flags(Flags {
+                        end_header: true,
+                        ..
+                    })
+                )
+            })
+            .unwrap_or(self.blocks.len());
+        self.blocks
+            .insert(position, Block::Header(Pair { key, val }));
+    }
+
+    /// Copy the headers of `src` with one of the given names (case insensitive) at the end of
+    /// the header section, in their order in `src`. They are copied in allocated Stores as they
+    /// can't reference the buffer of `src`.
+    ///
+    /// note: the headers are read from and inserted in the blocks, so this must be called before
+    /// prepare on both messages.
+    pub fn copy_headers_from<U: AsBuffer>(&mut self, src: &Kawa<U>, names: &[&[u8]]) {
+        let buf = src.storage.buffer();
+        for block in &src.blocks {
+            match block {
+                Block::Header(Pair { key, val })
+                    if names.iter().any(|name| key.equals_no_case(buf, name)) =>
+                {
+                    self.push_header(key.clone().capture(buf), val.clone().capture(buf));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Replace the key and value of the first header with the given name (case insensitive) by
+    /// static stores, or add the header if it is absent. Nothing is allocated, other headers
+    /// with the same name are kept.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn set_header_static(&mut self, key: &'static [u8], val: &'static [u8]) {
+        let buf = self.storage.buffer();
+        for block in &mut self.blocks {
+            match block {
+                Block::Header(header) if header.key.equals_no_case(buf, key) => {
+                    header.key = Store::Static(key);
+                    header.val = Store::Static(val);
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.push_header(Store::Static(key), Store::Static(val));
+    }
+
+    /// Split the value of the Authorization header in its scheme and credentials, at the first
+    /// space. Returns None if the header is absent or malformed.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn authorization(&self) -> Option<(&[u8], &[u8])> {
+        let value = self.find_header(b"authorization")?;
+        let space = value.iter().position(|c| *c == b' ')?;
+        let (scheme, credentials) = (&value[..space], &value[space + 1..]);
+        let spaces = credentials.iter().take_while(|c| **c == b' ').count();
+        let credentials = &credentials[spaces..];
+        if scheme.is_empty() || credentials.is_empty() {
+            return None;
+        }
+        Some((scheme, credentials))
+    }
+
+    /// Decode the user and password of a Basic Authorization header (RFC 7617).
+    /// Returns None if the header is absent, uses another scheme or is malformed.
+    pub fn basic_credentials(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let (scheme, credentials) = self.authorization()?;
+        if !compare_no_case(scheme, b"basic") {
+            return None;
+        }
+        let mut decoded = decode_base64(credentials)?;
+        let colon = decoded.iter().position(|c| *c == b':')?;
+        let password = decoded.split_off(colon + 1);
+        decoded.pop();
+        Some((decoded, password))
+    }
+
+    /// Returns the boundary parameter of a `multipart/*` Content-Type (RFC 2046, 5.1.1), without
+    /// its quotes. Returns None if the header is absent, is not multipart or has no boundary.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn multipart_boundary(&self) -> Option<&[u8]> {
+        let mut parts = self.find_header(b"content-type")?.split(|c| *c == b';');
+        let media_type = trim_ows(parts.next()?);
+        if media_type.len() < 10 || !compare_no_case(&media_type[..10], b"multipart/") {
+            return None;
+        }
+        let boundary = parts.map(trim_ows).find_map(|parameter| {
+            let equal = parameter.iter().position(|c| *c == b'=')?;
+            let (name, value) = (trim_ows(&parameter[..equal]), &parameter[equal + 1..]);
+            compare_no_case(name, b"boundary").then(|| trim_ows(value))
+        })?;
+        let boundary = match boundary {
+            [b'"', quoted @ .., b'"'] => quoted,
+            _ => boundary,
+        };
+        if boundary.is_empty() {
+            return None;
+        }
+        Some(boundary)
+    }
+
+    /// Iterate over the transfer codings listed in the TE headers (RFC 9110, 10.1.4), without
+    /// their parameters (e.g. `gzip;q=1, trailers` yields `gzip` and `trailers`). The codings
+    /// refused by the client with a zero weight (e.g. `gzip;q=0`) are skipped.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn te_codings(&self) -> impl Iterator<Item = &[u8]> {
+        self.header_values(b"te")
+            .flat_map(|value| value.split(|c| *c == b','))
+            .filter_map(|coding| {
+                let mut parameters = coding.split(|c| *c == b';');
+                let name = trim_ows(parameters.next().unwrap_or(coding));
+                let refused = parameters.map(trim_ows).any(|parameter| {
+                    parameter.len() >= 2
+                        && compare_no_case(&parameter[..2], b"q=")
+                        && is_zero_qvalue(trim_ows(&parameter[2..]))
+                });
+                (!name.is_empty() && !refused).then_some(name)
+            })
+    }
+
+    /// Iterate over the options listed in the Connection headers (e.g. `close`, `keep-alive` or
+    /// the names of hop-by-hop headers).
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn connection_options(&self) -> impl Iterator<Item = &[u8]> {
+        self.header_values(b"connection")
+            .flat_map(|value| value.split(|c| *c == b','))
+            .map(trim_ows)
+            .filter(|option| !option.is_empty())
+    }
+
+    /// Returns true if the message is a WebSocket opening handshake request (RFC 6455, 4.1): an
+    /// "upgrade" Connection option, a "websocket" Upgrade protocol and a Sec-WebSocket-Key header.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare and
+    /// before strip_hop_by_hop.
+    pub fn is_websocket_handshake(&self) -> bool {
+        matches!(self.detached.status_line, StatusLine::Request { .. })
+            && self
+                .connection_options()
+                .any(|option| compare_no_case(option, b"upgrade"))
+            && self.upgrades_to_websocket()
+            && self.find_header(b"sec-websocket-key").is_some()
+    }
+
+    /// Returns true if the message is a WebSocket opening handshake response accepting the
+    /// upgrade (RFC 6455, 4.2.2): a 101 status, a "websocket" Upgrade protocol and a
+    /// Sec-WebSocket-Accept header.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare and
+    /// before strip_hop_by_hop.
+    pub fn is_websocket_accept(&self) -> bool {
+        matches!(
+            self.detached.status_line,
+            StatusLine::Response { code: 101, .. }
+        ) && self.upgrades_to_websocket()
+            && self.find_header(b"sec-websocket-accept").is_some()
+    }
+
+    fn upgrades_to_websocket(&self) -> bool {
+        self.header_values(b"upgrade")
+            .flat_map(|value| value.split(|c| *c == b','))
+            .map(|protocol| trim_ows(protocol.split(|c| *c == b'/').next().unwrap_or(protocol)))
+            .any(|protocol| compare_no_case(protocol, b"websocket"))
+    }
+
+    /// Returns true if the connection should be closed after this message: HTTP/1.1 messages
+    /// are persistent unless they carry `Connection: close`, HTTP/1.0 messages are not unless
+    /// they carry `Connection: keep-alive` (RFC 9112, 9.3).
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare.
+    pub fn wants_close(&self) -> bool {
+        let mut close = false;
+        let mut keep_alive = false;
+        for option in self.connection_options() {
+            close |= compare_no_case(option, b"close");
+            keep_alive |= compare_no_case(option, b"keep-alive");
+        }
+        match self.detached.status_line.version() {
+            Version::V10 => close || !keep_alive,
+            _ => close,
+        }
+    }
+
+    /// Parse the timeout and max parameters of the Keep-Alive headers (RFC 2068, 19.7.1.1), used
+    /// to negotiate persistent HTTP/1.0 connections. Unknown or malformed parameters are skipped.
+    /// Returns None if the header is absent.
+    ///
+    /// note: the headers are read from the blocks, so this must be called before prepare and
+    /// before strip_hop_by_hop.
+    pub fn keep_alive_params(&self) -> Option<KeepAliveParams> {
+        let mut values = self.header_values(b"keep-alive").peekable();
+        values.peek()?;
+        let mut params = KeepAliveParams::default();
+        for parameter in values.flat_map(|value| value.split(|c| *c == b',')) {
+            let equal = match parameter.iter().position(|c| *c == b'=') {
+                Some(equal) => equal,
+                None => continue,
+            };
+            let (name, value) = (
+                trim_ows(&parameter[..equal]),
+                trim_ows(&parameter[equal + 1..]),
+            );
+            let value = match value {
+                [b'"', quoted @ .., b'"'] => quoted,
+                _ => value,
+            };
+            let value = std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse().ok());
+            if compare_no_case(name, b"timeout") {
+                params.timeout = value.or(params.timeout);
+            } else if compare_no_case(name, b"max") {
+                params.max = value.or(params.max);
+            }
+        }
+        Some(params)
+    }
+
+    /// Prepare a response Kawa to parse the answer to `req`, before parsing it: head_request and
+    /// connect_request are set from the request method.
+    ///
+    /// note: the method is read from the status line, so this must be called before the request
+    /// is prepared.
+    pub fn answer_to<U: AsBuffer>(&mut self, req: &Kawa<U>) {
+        let method = match &req.detached.status_line {
+            StatusLine::Request { method, .. } => method.data_opt(req.storage.buffer()),
+            _ => None,
+        };
+        self.head_request = method == Some(b"HEAD");
+        self.connect_request = method == Some(b"CONNECT");
+    }
+
+    /// Build a complete response to a request, typically to answer with an error. The response
+    /// uses the version of the request, carries a Content-Length and the given body, and a
+    /// Connection header matching the persistence of the request connection.
+    ///
+    /// All the Stores of the response are allocated, its buffer is empty.
+    /// note: the headers of the request are read from the blocks, so this must be called before
+    /// preparing the request.
+    pub fn error_response(req: &Kawa<T>, code: u16, reason: &[u8], body: &[u8]) -> Kawa<Vec<u8>> {
+        let version = match req.detached.status_line.version() {
+            Version::Unknown => Version::V11,
+            version => version,
+        };
+        let mut resp = Kawa::new(Kind::Response, Buffer::new(Vec::new()));
+        resp.detached.status_line = StatusLine::Response {
+            version,
+            code,
+            status: Store::from_string(code.to_string()),
+            reason: Store::from_slice(reason),
+        };
+        resp.blocks.push_back(Block::StatusLine);
+        resp.blocks.push_back(Block::Header(Pair {
+            key: Store::Static(b"Content-Length"),
+            val: Store::from_string(body.len().to_string()),
+        }));
+        let connection = match version {
+            Version::V20 => None,
+            _ if req.wants_close() => Some(&b"close"[..]),
+            Version::V10 => Some(&b"keep-alive"[..]),
+            _ => None,
+        };
+        if let Some(connection) = connection {
+            resp.blocks.push_back(Block::Header(Pair {
+                key: Store::Static(b"Connection"),
+                val: Store::Static(connection),
+            }));
+        }
+        resp.blocks.push_back(Block::Flags(Flags {
+            end_body: false,
+            end_chunk: false,
+            end_header: true,
+            end_stream: body.is_empty(),
+        }));
+        if !body.is_empty() {
+            resp.blocks.push_back(Block::Chunk(Chunk {
+                data: Store::from_slice(body),
+            }));
+            resp.blocks.push_back(Block::Flags(Flags {
+                end_body: true,
+                end_chunk: false,
+                end_header: false,
+                end_stream: true,
+            }));
+        }
+        resp.body_size = BodySize::Length(body.len());
+        resp.parsing_phase = ParsingPhase::Terminated;
+        resp
+    }
+
+    /// Elide the hop-by-hop headers (RFC 9110, 7.6.1): Connection, Keep-Alive,
+    /// Proxy-Connection, TE, Trailer, Upgrade and every header listed in a Connection header.
+    ///
+    /// note: Transfer-Encoding is kept as it describes how the body blocks are framed, converters
+    /// handle it. This must be called before prepare.
+    pub fn strip_hop_by_hop(&mut self) {
+        let buf = self.storage.buffer();
+        let mut listed = Vec::new();
+        for block in &self.blocks {
+            if let Block::Header(Pair { key, val }) = block {
+                if !key.equals_no_case(buf, b"connection") {
+                    continue;
+                }
+                for option in val.data(buf).split(|c| *c == b',') {
+                    let option = trim_ows(option);
+                    if !option.is_empty() {
+                        listed.push(option);
+                    }
+                }
+            }
+        }
+        let mut elided = Vec::new();
+        for (index, block) in self.blocks.iter().enumerate() {
+            if let Block::Header(Pair { key, .. }) = block {
+                let key = match key.data_opt(buf) {
+                    Some(key) => key,
+                    None => continue,
+                };
+                if compare_no_case(key, b"connection")
+                    || compare_no_case(key, b"keep-alive")
+                    || compare_no_case(key, b"proxy-connection")
+                    || compare_no_case(key, b"te")
+                    || compare_no_case(key, b"trailer")
+                    || compare_no_case(key, b"upgrade")
+                    || listed.iter().any(|option| compare_no_case(key, option))
+                {
+                    elided.push(index);
+                }
+            }
+        }
+        for index in elided {
+            if let Block::Header(header) = &mut self.blocks[index] {
+                header.elide();
+            }
+        }
+    }
+
+    /// Returns the [start, end) offsets in the buffer of the status line and the headers, up to
+    /// the empty line included and excluding the body, once the headers are entirely parsed by
+    /// the H1 parser. The bytes can be sliced directly from the buffer, e.g. to sign them. The
+    /// offsets follow Kawa::push_left, None is returned if the region was shifted out.
+    pub fn header_region(&self) -> Option<(usize, usize)> {
+        self.header_region.filter(|(start, end)| start < end)
+    }
+
     pub fn is_initial(&self) -> bool {
         self.parsing_phase == ParsingPhase::StatusLine
     }
@@ -170,6 +817,16 @@ impl<T: AsBuffer> Kawa<T> {
         self.body_size == BodySize::Chunked
     }
 
+    /// Returns true if the message upgrades the connection, see Kawa::upgrade
+    pub fn is_upgrade(&self) -> bool {
+        self.upgrade.is_some()
+    }
+
+    /// Returns true if the connection becomes a tunnel after the message, see Kawa::tunnel
+    pub fn is_tunnel(&self) -> bool {
+        self.tunnel
+    }
+
     pub fn is_main_phase(&self) -> bool {
         match self.parsing_phase {
             ParsingPhase::Body
@@ -187,6 +844,59 @@ impl<T: AsBuffer> Kawa<T> {
         matches!(self.parsing_phase, ParsingPhase::Error { .. })
     }
 
+    /// Returns the parsing error if the Kawa is in the error phase
+    pub fn error(&self) -> Option<ParsingError> {
+        match self.parsing_phase {
+            ParsingPhase::Error { marker, kind } => Some(ParsingError { marker, kind }),
+            _ => None,
+        }
+    }
+
+    /// For a Consuming error, returns up to `window` bytes of the buffer around the offset at
+    /// which the parsing failed, to log the offending region
+    pub fn error_context(&self, window: usize) -> Option<&[u8]> {
+        let index = match self.parsing_phase {
+            ParsingPhase::Error {
+                kind: ParsingErrorKind::Consuming { index },
+                ..
+            } => index as usize,
+            _ => return None,
+        };
+        let used = self.storage.used();
+        let start = index.saturating_sub(window / 2).min(used.len());
+        let end = (start + window).min(used.len());
+        Some(&used[start..end])
+    }
+
+    /// For a Consuming error, locate the offset at which the parsing failed in the live data of
+    /// the Buffer: its line and column (both starting at 1, lines are counted from the start of
+    /// the data) and the bytes of the offending line. Returns None for other errors.
+    pub fn error_location(&self) -> Option<ErrorContext<'_>> {
+        let index = match self.parsing_phase {
+            ParsingPhase::Error {
+                kind: ParsingErrorKind::Consuming { index },
+                ..
+            } => index as usize,
+            _ => return None,
+        };
+        let data = self.storage.data();
+        let offset = index.checked_sub(self.storage.start)?.min(data.len());
+        let (before, after) = data.split_at(offset);
+        let line_start = before
+            .iter()
+            .rposition(|c| *c == b'\n')
+            .map_or(0, |lf| lf + 1);
+        let line_end = after
+            .iter()
+            .position(|c| *c == b'\r' || *c == b'\n')
+            .map_or(data.len(), |end| offset + end);
+        Some(ErrorContext {
+            line: before.iter().filter(|c| **c == b'\n').count() + 1,
+            column: offset - line_start + 1,
+            line_bytes: &data[line_start..line_end],
+        })
+    }
+
     pub fn is_terminated(&self) -> bool {
         self.parsing_phase == ParsingPhase::Terminated
     }
@@ -195,17 +905,83 @@ impl<T: AsBuffer> Kawa<T> {
         self.blocks.is_empty() && self.out.is_empty()
     }
 
-    /// Completely reset the Kawa state and storage.
+    /// Capture the parser cursor, to resume the parsing with Kawa::restore_state after the
+    /// backing buffer is swapped (e.g. double buffering).
+    ///
+    /// note: the blocks are kept in Kawa and their Store::Slice hold offsets relative to the
+    /// buffer, so the new buffer must hold the same bytes at the same offsets, from start to end.
+    pub fn resume_state(&self) -> ParserState {
+        ParserState {
+            parsing_phase: self.parsing_phase,
+            expects: self.expects,
+            body_size: self.body_size,
+            head: self.storage.head,
+            header_count: self.header_count,
+            line_length: self.line_length,
+            line_scanned: self.line_scanned,
+        }
+    }
+
+    /// Restore a parser cursor captured with Kawa::resume_state, see its note on offsets.
+    pub fn restore_state(&mut self, state: ParserState) {
+        self.parsing_phase = state.parsing_phase;
+        self.expects = state.expects;
+        self.body_size = state.body_size;
+        self.storage.head = state.head;
+        self.header_count = state.header_count;
+        self.line_length = state.line_length;
+        self.line_scanned = state.line_scanned;
+    }
+
+    /// Reset the Kawa state to parse a new message, the storage is left untouched so that the
+    /// bytes of a pipelined message already read after the current one are kept. The caller is
+    /// responsible for consuming the bytes of the current message (see Kawa::reset otherwise).
     pub fn clear(&mut self) {
-        // self.storage.clear();
         self.blocks.clear();
         self.out.clear();
         self.detached.jar.clear();
         self.detached.status_line = StatusLine::Unknown;
         self.expects = 0;
         self.consumed = false;
+        self.header_count = 0;
+        self.line_length = 0;
+        self.line_scanned = 0;
         self.parsing_phase = ParsingPhase::StatusLine;
         self.body_size = BodySize::Empty;
+        self.upgrade = None;
+        self.tunnel = false;
+        self.ignored_framing = None;
+        self.head_request = false;
+        self.connect_request = false;
+        self.header_region = None;
+    }
+
+    /// Prepare the Kawa to parse the next message pipelined after the current one: the state is
+    /// cleared while the bytes following the current message are kept in the storage.
+    /// Returns false, leaving the Kawa untouched, if the current message is not terminated or if
+    /// its blocks and output are not entirely converted and consumed.
+    pub fn next_message(&mut self) -> bool {
+        if !self.is_terminated() || !self.is_completed() {
+            return false;
+        }
+        self.clear();
+        true
+    }
+
+    /// Completely reset the Kawa state and storage, discarding any byte left in the buffer.
+    pub fn reset(&mut self) {
+        self.storage.clear();
+        self.clear();
+    }
+
+    /// Completely reset the Kawa state like clear, and shrink the capacity of the blocks, out and
+    /// jar vectors down to `capacity`, so that the memory grown by a message with many headers
+    /// is not kept by an idle Kawa.
+    pub fn clear_and_shrink(&mut self, capacity: usize) {
+        self.clear();
+        self.blocks.shrink_to(capacity);
+        self.out.shrink_to(capacity);
+        self.detached.jar.shrink_to(capacity);
     }
 }
 
@@ -220,11 +996,58 @@ impl<T: AsBuffer + Clone> Clone for Kawa<T> {
             expects: self.expects,
             parsing_phase: self.parsing_phase,
             body_size: self.body_size,
+            upgrade: self.upgrade.clone(),
+            tunnel: self.tunnel,
+            ignored_framing: self.ignored_framing,
+            header_region: self.header_region,
             consumed: self.consumed,
+            max_headers: self.max_headers,
+            header_count: self.header_count,
+            max_header_line: self.max_header_line,
+            max_uri_length: self.max_uri_length,
+            max_header_section: self.max_header_section,
+            role: self.role,
+            strict_host: self.strict_host,
+            skip_invalid_headers: self.skip_invalid_headers,
+            head_request: self.head_request,
+            connect_request: self.connect_request,
+            line_length: self.line_length,
+            line_scanned: self.line_scanned,
         }
     }
 }
 
+/// Result of a Kawa::prepare call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrepareOutcome {
+    /// Number of blocks for which the converter returned true
+    pub converted: usize,
+    /// Whether the converter stopped the conversion before the end of the blocks
+    pub paused: bool,
+}
+
+/// Parameters of a Keep-Alive header returned by Kawa::keep_alive_params
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeepAliveParams {
+    /// Number of seconds an idle connection is kept open
+    pub timeout: Option<u64>,
+    /// Number of requests allowed on the connection
+    pub max: Option<u64>,
+}
+
+/// Parser cursor returned by Kawa::resume_state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserState {
+    pub parsing_phase: ParsingPhase,
+    pub expects: usize,
+    pub body_size: BodySize,
+    /// Offset of the first unparsed byte in the buffer
+    pub head: usize,
+    header_count: usize,
+    line_length: usize,
+    line_scanned: usize,
+}
+
 /// Separate the content of the StatusLine and the crumbs from all the cookies from the stream of
 /// Blocks. It allows better indexing, persistance and reordering of data. However it is a double
 /// edge sword as it currently enables some unwanted/unsafe behavior such as Slice desync and over
@@ -241,6 +1064,25 @@ pub enum Kind {
     Response,
 }
 
+/// Role of the receiver of the requests parsed by the H1 parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Absolute-form request targets are rejected (RFC 7230, 5.3.2), they are only sent to
+    /// proxies. The asterisk-form of OPTIONS and the authority-form of CONNECT are accepted.
+    Origin,
+    /// Every request target form is accepted, this is the default.
+    Proxy,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Request => "request",
+            Kind::Response => "response",
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ParsingPhaseMarker {
     StatusLine,
@@ -253,10 +1095,37 @@ pub enum ParsingPhaseMarker {
     Error,
 }
 
+impl fmt::Display for ParsingPhaseMarker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParsingPhaseMarker::StatusLine => "status line",
+            ParsingPhaseMarker::Headers => "headers",
+            ParsingPhaseMarker::Cookies => "cookies",
+            ParsingPhaseMarker::Body => "body",
+            ParsingPhaseMarker::Chunks => "chunks",
+            ParsingPhaseMarker::Trailers => "trailers",
+            ParsingPhaseMarker::Terminated => "terminated",
+            ParsingPhaseMarker::Error => "error",
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ParsingErrorKind {
-    Consuming { index: u32 },
-    Processing { message: &'static str },
+    Consuming {
+        index: u32,
+    },
+    Processing {
+        message: &'static str,
+    },
+    TooManyHeaders,
+    HeaderLineTooLong,
+    /// An H1 request parser received the HTTP/2 connection preface, the client speaks HTTP/2
+    H2Preface,
+    /// The request target is longer than Kawa::max_uri_length
+    UriTooLong,
+    /// The status line and headers are larger than Kawa::max_header_section
+    HeaderSectionTooLarge,
 }
 
 impl From<&'static str> for ParsingErrorKind {
@@ -312,6 +1181,53 @@ impl ParsingPhase {
     }
 }
 
+/// Renders the name of the phase, and the phase in which the error occured for errors
+impl fmt::Display for ParsingPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsingPhase::Error { marker, .. } => write!(f, "error in {marker}"),
+            phase => phase.marker().fmt(f),
+        }
+    }
+}
+
+/// Owned parsing error, extracted from a Kawa in the error phase with Kawa::error, that can be
+/// propagated as a std::error::Error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsingError {
+    /// The phase in which the parsing failed
+    pub marker: ParsingPhaseMarker,
+    pub kind: ParsingErrorKind,
+}
+
+impl fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "parsing error in {}: ", self.marker)?;
+        match self.kind {
+            ParsingErrorKind::Consuming { index } => write!(f, "unexpected byte at offset {index}"),
+            ParsingErrorKind::Processing { message } => f.write_str(message),
+            ParsingErrorKind::TooManyHeaders => f.write_str("too many headers"),
+            ParsingErrorKind::HeaderLineTooLong => f.write_str("header line too long"),
+            ParsingErrorKind::H2Preface => f.write_str("received the HTTP/2 connection preface"),
+            ParsingErrorKind::UriTooLong => f.write_str("request target too long"),
+            ParsingErrorKind::HeaderSectionTooLarge => f.write_str("header section too large"),
+        }
+    }
+}
+
+impl std::error::Error for ParsingError {}
+
+/// Location of a Consuming parsing error returned by Kawa::error_location
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorContext<'a> {
+    /// Line of the error, starting at 1
+    pub line: usize,
+    /// Column of the error in its line, starting at 1
+    pub column: usize,
+    /// The offending line, without its line ending
+    pub line_bytes: &'a [u8],
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BodySize {
     Empty,
@@ -338,6 +1254,7 @@ impl Block {
             }
             Block::ChunkHeader(header) => {
                 header.length.push_left(amount);
+                header.extensions.push_left(amount);
             }
             Block::Chunk(chunk) => {
                 chunk.data.push_left(amount);
@@ -366,6 +1283,117 @@ pub enum StatusLine {
 }
 
 impl StatusLine {
+    pub fn version(&self) -> Version {
+        match self {
+            StatusLine::Request { version, .. } | StatusLine::Response { version, .. } => *version,
+            StatusLine::Unknown => Version::Unknown,
+        }
+    }
+
+    /// Split the authority of a request in host and port, at the last colon. IPv6 literals keep
+    /// their brackets and the colons they contain are not treated as port separators.
+    /// Returns (Store::Empty, None) for responses or if the authority is missing.
+    pub fn authority_parts(&self, buf: &[u8]) -> (Store, Option<Store>) {
+        let authority = match self {
+            StatusLine::Request { authority, .. } => authority,
+            _ => return (Store::Empty, None),
+        };
+        let data = match authority.data_opt(buf) {
+            Some(data) => data,
+            None => return (Store::Empty, None),
+        };
+        let (host, port) = split_authority(data);
+        if host.len() == data.len() {
+            return (authority.clone(), None);
+        }
+        let (host, rest) = authority.clone().split(host.len());
+        (host, port.map(|_| rest.split(1).1))
+    }
+
+    /// Rebuild the target URI of a request from its parts (RFC 9112, 3.2), allocated in a Store:
+    /// `scheme://authority/path` for the origin and absolute forms with an authority (the scheme
+    /// of an absolute-form target, http otherwise), the path alone without authority, the
+    /// authority for CONNECT requests and `*` for server-wide OPTIONS requests.
+    /// Returns Store::Empty for responses.
+    pub fn request_target(&self, buf: &[u8]) -> Store {
+        let (method, authority, path, uri) = match self {
+            StatusLine::Request {
+                method,
+                authority,
+                path,
+                uri,
+                ..
+            } => (method, authority, path, uri),
+            _ => return Store::Empty,
+        };
+        let authority = authority.data_opt(buf).unwrap_or_default();
+        let path = path.data_opt(buf).unwrap_or_default();
+        let uri = uri.data_opt(buf).unwrap_or_default();
+        if method.equals(buf, b"CONNECT") {
+            return Store::from_slice(authority);
+        }
+        if uri == b"*" {
+            return Store::Static(b"*");
+        }
+        if authority.is_empty() {
+            return Store::from_slice(path);
+        }
+        let scheme = match uri.windows(3).position(|window| window == b"://") {
+            Some(end)
+                if uri[..end]
+                    .iter()
+                    .all(|c| c.is_ascii_alphanumeric() || b"+-.".contains(c)) =>
+            {
+                &uri[..end]
+            }
+            _ => b"http",
+        };
+        let mut target = scheme.to_ascii_lowercase();
+        target.extend_from_slice(b"://");
+        target.extend_from_slice(authority);
+        // the empty path of a server-wide OPTIONS request in absolute-form is replaced by "*"
+        if path != b"*" {
+            target.extend_from_slice(path);
+        }
+        Store::from_vec(target)
+    }
+
+    pub fn push_left(&mut self, amount: u32) {
+        match self {
+            StatusLine::Request {
+                method,
+                authority,
+                path,
+                uri,
+                ..
+            } => {
+                method.push_left(amount);
+                authority.push_left(amount);
+                path.push_left(amount);
+                uri.push_left(amount);
+            }
+            StatusLine::Response { status, reason, .. } => {
+                status.push_left(amount);
+                reason.push_left(amount);
+            }
+            StatusLine::Unknown => {}
+        }
+    }
+
+    fn stores(&self) -> Vec<&Store> {
+        match self {
+            StatusLine::Request {
+                method,
+                authority,
+                path,
+                uri,
+                ..
+            } => vec![method, authority, path, uri],
+            StatusLine::Response { status, reason, .. } => vec![status, reason],
+            StatusLine::Unknown => Vec::new(),
+        }
+    }
+
     pub fn pop(&mut self) -> StatusLine {
         match self {
             StatusLine::Request { version, .. } => {
@@ -408,11 +1436,24 @@ impl Pair {
     pub fn is_elided(&self) -> bool {
         self.key.is_empty()
     }
+
+    /// Whether a cookie crumb is converted with a "=" between its key and value. A crumb without
+    /// "=" has an empty key, a crumb with an empty name has the EMPTY_CRUMB_NAME Static key.
+    // Store::is_empty checks for the Empty variant, not for an empty content
+    #[allow(clippy::len_zero)]
+    pub fn has_crumb_separator(&self) -> bool {
+        self.key.len() != 0 || self.key.is_static()
+    }
 }
 
+/// Size line of a chunk, the last chunk only gets one if it carries extensions and it is then
+/// followed by the Flags ending the body
 #[derive(Debug, Clone)]
 pub struct ChunkHeader {
+    /// hexadecimal size of the chunk
     pub length: Store,
+    /// chunk extensions without the leading semicolon, Store::Empty if there are none
+    pub extensions: Store,
 }
 
 #[derive(Debug, Clone)]
@@ -457,10 +1498,21 @@ pub enum Store {
     Detached(Slice),
     Static(&'static [u8]),
     Alloc(Box<[u8]>, u32),
-    #[cfg(feature = "rc-alloc")]
+    #[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
     Shared(Rc<[u8]>, u32),
+    /// Same as Shared, but can be sent across threads: enabling arc-alloc compiles Shared out,
+    /// so Store and Kawa are Send and Sync even alongside rc-alloc.
+    #[cfg(feature = "arc-alloc")]
+    SharedArc(Arc<[u8]>, u32),
 }
 
+#[cfg(not(all(feature = "rc-alloc", not(feature = "arc-alloc"))))]
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Store>();
+    assert_send_sync::<Kawa<Vec<u8>>>();
+};
+
 impl Store {
     pub fn new_slice(buffer: &[u8], data: &[u8]) -> Store {
         Store::Slice(Slice::new(buffer, data))
@@ -470,6 +1522,10 @@ impl Store {
         Store::Detached(Slice::new(buffer, data))
     }
 
+    pub fn from_static(data: &'static [u8]) -> Store {
+        Store::Static(data)
+    }
+
     pub fn from_vec(data: Vec<u8>) -> Store {
         Store::Alloc(data.into_boxed_slice(), 0)
     }
@@ -482,6 +1538,14 @@ impl Store {
         Store::Alloc(data.into_bytes().into_boxed_slice(), 0)
     }
 
+    /// Offset in the Buffer of the data referenced by a Slice or Detached Store
+    fn slice_start(&self) -> Option<usize> {
+        match self {
+            Store::Slice(slice) | Store::Detached(slice) => Some(slice.start as usize),
+            _ => None,
+        }
+    }
+
     pub fn push_left(&mut self, amount: u32) {
         match self {
             Store::Slice(slice) => {
@@ -500,8 +1564,10 @@ impl Store {
             Store::Slice(s) | Store::Detached(s) => s.len(),
             Store::Static(s) => s.len(),
             Store::Alloc(s, i) => s.len() - *i as usize,
-            #[cfg(feature = "rc-alloc")]
+            #[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
             Store::Shared(s, i) => s.len() - *i as usize,
+            #[cfg(feature = "arc-alloc")]
+            Store::SharedArc(s, i) => s.len() - *i as usize,
         }
     }
 
@@ -509,14 +1575,20 @@ impl Store {
         matches!(self, Store::Empty)
     }
 
+    pub fn is_static(&self) -> bool {
+        matches!(self, Store::Static(_))
+    }
+
     pub fn data<'a>(&'a self, buf: &'a [u8]) -> &'a [u8] {
         match self {
             Store::Empty => unreachable!(),
             Store::Slice(slice) | Store::Detached(slice) => slice.data(buf),
             Store::Static(data) => data,
             Store::Alloc(data, index) => &data[*index as usize..],
-            #[cfg(feature = "rc-alloc")]
+            #[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
             Store::Shared(data, index) => &data[*index as usize..],
+            #[cfg(feature = "arc-alloc")]
+            Store::SharedArc(data, index) => &data[*index as usize..],
         }
     }
     pub fn data_opt<'a>(&'a self, buf: &'a [u8]) -> Option<&'a [u8]> {
@@ -525,11 +1597,25 @@ impl Store {
             Store::Slice(slice) | Store::Detached(slice) => slice.data_opt(buf),
             Store::Static(data) => Some(data),
             Store::Alloc(data, index) => Some(&data[*index as usize..]),
-            #[cfg(feature = "rc-alloc")]
+            #[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
             Store::Shared(data, index) => Some(&data[*index as usize..]),
+            #[cfg(feature = "arc-alloc")]
+            Store::SharedArc(data, index) => Some(&data[*index as usize..]),
         }
     }
 
+    /// Returns true if the content of the Store is `other`. Store::Empty matches nothing, so
+    /// elided headers are never found.
+    pub fn equals(&self, buf: &[u8], other: &[u8]) -> bool {
+        self.data_opt(buf) == Some(other)
+    }
+
+    /// Same as equals, ignoring the ASCII case
+    pub fn equals_no_case(&self, buf: &[u8], other: &[u8]) -> bool {
+        self.data_opt(buf)
+            .map_or(false, |data| compare_no_case(data, other))
+    }
+
     pub fn capture(self, buf: &[u8]) -> Store {
         match self {
             Store::Slice(slice) | Store::Detached(slice) => Store::from_slice(slice.data(buf)),
@@ -557,6 +1643,17 @@ impl Store {
         }
     }
 
+    /// Concatenate two Stores in a Store::Alloc, each Store is read from its own buffer.
+    /// Concatenating with Store::Empty doesn't allocate, except to capture a Slice of `other`,
+    /// the result may not reference `other_buf`. Two Empty Stores give Store::Empty.
+    pub fn concat(self, buf: &[u8], other: &Store, other_buf: &[u8]) -> Store {
+        match (self.data_opt(buf), other.data_opt(other_buf)) {
+            (_, None) => self,
+            (None, Some(_)) => other.clone().capture(other_buf),
+            (Some(data), Some(other_data)) => Store::from_vec([data, other_data].concat()),
+        }
+    }
+
     pub fn split(self, at: usize) -> (Store, Store) {
         let at32 = at as u32;
         match self {
@@ -580,11 +1677,16 @@ impl Store {
                 Store::from_slice(&s[i as usize..i as usize + at]),
                 Store::Alloc(s, i + at32),
             ),
-            #[cfg(feature = "rc-alloc")]
+            #[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
             Store::Shared(s, i) => (
                 Store::from_slice(&s[i as usize..i as usize + at]),
                 Store::Shared(s, i + at32),
             ),
+            #[cfg(feature = "arc-alloc")]
+            Store::SharedArc(s, i) => (
+                Store::from_slice(&s[i as usize..i as usize + at]),
+                Store::SharedArc(s, i + at32),
+            ),
         }
     }
 
@@ -613,7 +1715,7 @@ impl Store {
                     (0, Some(Store::Alloc(data, index + amount as u32)))
                 }
             }
-            #[cfg(feature = "rc-alloc")]
+            #[cfg(all(feature = "rc-alloc", not(feature = "arc-alloc")))]
             Store::Shared(data, index) => {
                 if amount >= data.len() - index as usize {
                     (amount - data.len() + index as usize, None)
@@ -621,6 +1723,14 @@ impl Store {
                     (0, Some(Store::Shared(data, index + amount as u32)))
                 }
             }
+            #[cfg(feature = "arc-alloc")]
+            Store::SharedArc(data, index) => {
+                if amount >= data.len() - index as usize {
+                    (amount - data.len() + index as usize, None)
+                } else {
+                    (0, Some(Store::SharedArc(data, index + amount as u32)))
+                }
+            }
         }
     }
 }
@@ -6,7 +6,7 @@ use std::{
     slice::from_raw_parts_mut,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct VecDeque<T: Sized> {
     tail: usize,
     head: usize,
@@ -20,6 +20,10 @@ fn wrap_index(index: usize, cap: usize) -> usize {
     index & (cap - 1)
 }
 
+// the ring is owned like the buffer of a Vec
+unsafe impl<T: Send> Send for VecDeque<T> {}
+unsafe impl<T: Sync> Sync for VecDeque<T> {}
+
 impl<T: Sized> Default for VecDeque<T> {
     fn default() -> Self {
         Self::new()
@@ -60,6 +64,38 @@ impl<T: Sized> VecDeque<T> {
         self.len == self.cap
     }
     #[inline]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            unsafe { Some(&*self.ptr.add(wrap_index(self.tail + 1 + index, self.cap))) }
+        } else {
+            None
+        }
+    }
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len {
+            unsafe { Some(&mut *self.ptr.add(wrap_index(self.tail + 1 + index, self.cap))) }
+        } else {
+            None
+        }
+    }
+    #[inline]
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+    #[inline]
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(0)
+    }
+    #[inline]
+    pub fn back(&self) -> Option<&T> {
+        self.get(self.len.wrapping_sub(1))
+    }
+    #[inline]
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(self.len.wrapping_sub(1))
+    }
+    #[inline]
     pub fn push_back(&mut self, element: T) {
         unsafe { self.ptr.add(self.head).write(element) };
         self.head = wrap_index(self.head + 1, self.cap);
@@ -95,6 +131,15 @@ impl<T: Sized> VecDeque<T> {
         self.len -= 1;
         unsafe { Some(self.ptr.add(self.tail).read()) }
     }
+    pub fn insert(&mut self, index: usize, element: T) {
+        assert!(index <= self.len);
+        self.push_back(element);
+        for i in (index..self.len - 1).rev() {
+            let a = wrap_index(self.tail + 1 + i, self.cap);
+            let b = wrap_index(self.tail + 2 + i, self.cap);
+            unsafe { std::ptr::swap(self.ptr.add(a), self.ptr.add(b)) };
+        }
+    }
     #[inline]
     pub fn clear(&mut self) {
         let mut index = self.tail;
@@ -106,6 +151,42 @@ impl<T: Sized> VecDeque<T> {
         self.head = 0;
         self.tail = self.cap - 1;
     }
+    /// Drops the elements after the first `len` ones, does nothing if there are no more than
+    /// `len` elements
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let removed = self.len - len;
+        // the deque is shrunk first, so a panicking drop can't lead to a double drop
+        self.len = len;
+        self.head = wrap_index(self.tail + 1 + len, self.cap);
+        for i in 0..removed {
+            let index = wrap_index(self.head + i, self.cap);
+            unsafe { self.ptr.add(index).drop_in_place() };
+        }
+    }
+    /// Keeps only the elements for which `f` returns true, in order. `f` is called exactly once
+    /// per element, front to back.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        // rejected elements are swapped towards the back and truncated, every slot holds a
+        // valid element if `f` panics
+        let mut kept = 0;
+        for i in 0..self.len {
+            if f(&self[i]) {
+                if kept != i {
+                    let a = wrap_index(self.tail + 1 + kept, self.cap);
+                    let b = wrap_index(self.tail + 1 + i, self.cap);
+                    unsafe { std::ptr::swap(self.ptr.add(a), self.ptr.add(b)) };
+                }
+                kept += 1;
+            }
+        }
+        self.truncate(kept);
+    }
     #[inline]
     pub fn reserve(&mut self, capacity: usize) {
         if self.cap < capacity {
@@ -114,11 +195,15 @@ impl<T: Sized> VecDeque<T> {
     }
     #[inline]
     pub fn grow(&mut self, additional: usize) {
-        let target = self.cap + additional;
-        let old_layout = unsafe { Layout::array::<T>(self.cap).unwrap_unchecked() };
+        let Some(target) = self.cap.checked_add(additional) else {
+            panic!("capacity overflow");
+        };
         let mut cap = self.cap;
         while cap < target {
-            cap *= 2;
+            let Some(doubled) = cap.checked_mul(2) else {
+                panic!("capacity overflow");
+            };
+            cap = doubled;
         }
         let new_layout = Layout::array::<T>(cap);
 
@@ -128,7 +213,28 @@ impl<T: Sized> VecDeque<T> {
         if usize::BITS < 64 && new_layout.size() > isize::MAX as usize {
             panic!("capacity overflow");
         }
-
+        self.reallocate(new_layout, cap);
+    }
+    /// Reduces the capacity to the smallest power of two able to hold `min_capacity` elements,
+    /// and at least the current elements. Does nothing if the capacity is already lower.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let cap = min_capacity.max(self.len).saturating_add(1).max(2);
+        let cap = cap.checked_next_power_of_two().unwrap_or(self.cap);
+        if cap >= self.cap {
+            return;
+        }
+        let new_layout = unsafe { Layout::array::<T>(cap).unwrap_unchecked() };
+        self.reallocate(new_layout, cap);
+    }
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        // the ring is never full
+        self.cap - 1
+    }
+    /// Moves the elements at the start of a new ring of `cap` slots, `cap` must be greater than
+    /// the number of elements
+    fn reallocate(&mut self, new_layout: Layout, cap: usize) {
+        let old_layout = unsafe { Layout::array::<T>(self.cap).unwrap_unchecked() };
         self.ptr = unsafe {
             let new_ptr = alloc(new_layout) as *mut T;
             let (front, back) = self.as_slices();
@@ -144,7 +250,7 @@ impl<T: Sized> VecDeque<T> {
     #[inline]
     pub fn as_slices(&mut self) -> (&mut [T], &mut [T]) {
         let tail = wrap_index(self.tail + 1, self.cap);
-        let contiguous = tail < self.head || tail == 0;
+        let contiguous = tail < self.head || tail == 0 || self.is_empty();
         if contiguous {
             unsafe { (from_raw_parts_mut(self.ptr.add(tail), self.len), &mut []) }
         } else {
@@ -167,7 +273,7 @@ impl<T: Sized> VecDeque<T> {
         }
     }
     #[inline]
-    pub fn iter_mut(&self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<T> {
         IterMut {
             remaining: self.len,
             index: self.tail,
@@ -195,16 +301,66 @@ impl<T: Sized> VecDeque<T> {
     }
 }
 
+impl<T: Clone> Clone for VecDeque<T> {
+    fn clone(&self) -> Self {
+        let mut clone = Self::with_capacity(self.cap);
+        for element in self {
+            clone.push_back(element.clone());
+        }
+        clone
+    }
+}
+
+impl<T: PartialEq> PartialEq for VecDeque<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Sized> Extend<T> for VecDeque<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for element in iter {
+            self.push_back(element);
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a> Extend<&'a T> for VecDeque<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<T: Sized> FromIterator<T> for VecDeque<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut deque = Self::new();
+        deque.extend(iter);
+        deque
+    }
+}
+
+impl<T: Sized> Drop for VecDeque<T> {
+    fn drop(&mut self) {
+        self.clear();
+        unsafe {
+            dealloc(
+                self.ptr as *mut u8,
+                Layout::array::<T>(self.cap).unwrap_unchecked(),
+            )
+        };
+    }
+}
+
 impl<T: Sized> Index<usize> for VecDeque<T> {
     type Output = T;
     fn index(&self, i: usize) -> &Self::Output {
-        unsafe { &*self.ptr.add(wrap_index(self.tail + 1 + i, self.cap)) }
+        self.get(i).expect("Out of bounds access")
     }
 }
 
 impl<T: Sized> IndexMut<usize> for VecDeque<T> {
     fn index_mut(&mut self, i: usize) -> &mut T {
-        unsafe { &mut *self.ptr.add(wrap_index(self.tail + 1 + i, self.cap)) }
+        self.get_mut(i).expect("Out of bounds access")
     }
 }
 
@@ -230,6 +386,14 @@ pub struct Drain<'a, T: 'a> {
     _marker: PhantomData<&'a ()>,
 }
 
+// the iterators borrow the ring like the ones of a slice
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+unsafe impl<'a, T: Send> Send for Drain<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Drain<'a, T> {}
+
 impl<'a, T: Sized> IntoIterator for &'a VecDeque<T> {
     type Item = &'a T;
     type IntoIter = Iter<'a, T>;
@@ -284,6 +448,15 @@ impl<'a, T: Sized> Iterator for Drain<'a, T> {
     }
 }
 
+/// The elements not yielded by the Drain are dropped with it
+impl<'a, T: Sized> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        for element in self {
+            drop(element);
+        }
+    }
+}
+
 #[cfg(test)]
 macro_rules! assert_vec {
     ($v:ident: $($e:expr),* ; $cap:expr) => {
@@ -297,6 +470,26 @@ macro_rules! assert_vec {
     };
 }
 
+#[test]
+fn custom_vecdeque_capacity_overflow() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut v = VecDeque::with_capacity(4);
+    v.push_back(1u8);
+    v.push_back(2);
+    // the target capacity overflows, then the doubling of the capacity does
+    for additional in [usize::MAX, usize::MAX / 2] {
+        let result = catch_unwind(AssertUnwindSafe(|| v.grow(additional)));
+        let message = result
+            .expect_err("GROW")
+            .downcast::<&str>()
+            .expect("MESSAGE");
+        assert_eq!(*message, "capacity overflow");
+        // the deque is left untouched
+        assert_vec!(v: 1, 2; 4);
+    }
+}
+
 #[test]
 fn custom_vecdeque() {
     let mut v = VecDeque::with_capacity(4);
@@ -339,4 +532,299 @@ fn custom_vecdeque() {
     v.push_back(3);
     v.reserve(5);
     assert_vec!(v: 1, 2, 3; 8);
+    let mut v = VecDeque::with_capacity(4);
+    v.push_back(2);
+    v.push_front(1);
+    v.insert(1, 3);
+    v.insert(3, 4);
+    v.insert(0, 0);
+    assert_vec!(v: 0, 1, 3, 2, 4; 8);
+}
+
+#[test]
+fn custom_vecdeque_drop() {
+    use std::rc::Rc;
+
+    let counter = Rc::new(());
+    let mut v = VecDeque::with_capacity(2);
+    for _ in 0..5 {
+        v.push_back(counter.clone());
+    }
+    v.pop_front();
+    v.push_front(counter.clone());
+    let clone = v.clone();
+    assert_eq!(Rc::strong_count(&counter), 11);
+    drop(clone);
+    assert_eq!(Rc::strong_count(&counter), 6);
+    // the elements not yielded by a drain dropped early are dropped with it
+    let mut drain = v.drain(..);
+    drain.next();
+    drop(drain);
+    assert!(v.is_empty());
+    assert_eq!(Rc::strong_count(&counter), 1);
+    for _ in 0..3 {
+        v.push_front(counter.clone());
+    }
+    drop(v);
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn custom_vecdeque_clone() {
+    // the ring wraps around: 3 to 7 at the end of the buffer, 8 at its start
+    let mut v = VecDeque::with_capacity(8);
+    for i in 0..6 {
+        v.push_back(i);
+    }
+    for _ in 0..4 {
+        v.pop_front();
+    }
+    v.push_back(6);
+    v.push_back(7);
+    v.push_back(8);
+    v.push_front(3);
+    assert!(v.head < v.tail);
+    assert_vec!(v: 3, 4, 5, 6, 7, 8; 8);
+
+    let mut clone = v.clone();
+    assert_vec!(clone: 3, 4, 5, 6, 7, 8; 8);
+    assert!(clone == v);
+    clone[5] = 0;
+    assert!(clone != v);
+    assert_vec!(v: 3, 4, 5, 6, 7, 8; 8);
+    clone[5] = 8;
+    assert!(clone == v);
+    clone.pop_back();
+    assert!(clone != v);
+}
+
+#[test]
+fn custom_vecdeque_retain_truncate() {
+    use std::rc::Rc;
+
+    // builds both deques so that the custom ring wraps around
+    fn wrapped() -> (VecDeque<u32>, std::collections::VecDeque<u32>) {
+        let mut v = VecDeque::with_capacity(8);
+        let mut s = std::collections::VecDeque::new();
+        for i in 0..5 {
+            v.push_back(i);
+        }
+        for _ in 0..5 {
+            v.pop_front();
+        }
+        for i in 1..=6 {
+            v.push_back(i);
+            s.push_back(i);
+        }
+        v.push_front(0);
+        s.push_front(0);
+        assert!(v.head <= v.tail);
+        (v, s)
+    }
+    fn assert_same(v: &VecDeque<u32>, s: &std::collections::VecDeque<u32>) {
+        assert!(v.iter().eq(s.iter()));
+        assert_eq!(v.len(), s.len());
+    }
+
+    let predicates: [fn(&u32) -> bool; 5] = [
+        |_| true,
+        |_| false,
+        |x| x % 2 == 0,
+        |x| *x > 3,
+        |x| *x == 2 || *x == 6,
+    ];
+    for f in predicates {
+        let (mut v, mut s) = wrapped();
+        let mut seen = Vec::new();
+        v.retain(|x| {
+            seen.push(*x);
+            f(x)
+        });
+        s.retain(f);
+        assert_same(&v, &s);
+        assert_eq!(seen, [0, 1, 2, 3, 4, 5, 6]);
+        // the deque stays usable at both ends
+        v.push_back(7);
+        v.push_front(8);
+        s.push_back(7);
+        s.push_front(8);
+        assert_same(&v, &s);
+    }
+    for len in 0..=8 {
+        let (mut v, mut s) = wrapped();
+        v.truncate(len);
+        s.truncate(len);
+        assert_same(&v, &s);
+        v.push_back(7);
+        s.push_back(7);
+        assert_same(&v, &s);
+    }
+
+    // removed elements are dropped exactly once
+    let counter = Rc::new(());
+    let mut v = VecDeque::with_capacity(4);
+    for i in 0..6 {
+        v.push_back((i, counter.clone()));
+    }
+    v.retain(|(i, _)| i % 3 != 0);
+    assert_eq!(Rc::strong_count(&counter), 5);
+    assert!(v.iter().map(|(i, _)| *i).eq([1, 2, 4, 5]));
+    v.truncate(1);
+    assert_eq!(Rc::strong_count(&counter), 2);
+    drop(v);
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn custom_vecdeque_differential() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // xorshift, so the sequences are reproducible without any dependency
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut random = move |bound: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % bound as u64) as usize
+    };
+
+    for round in 0..64 {
+        let mut v = VecDeque::<usize>::with_capacity(2);
+        let mut s = std::collections::VecDeque::<usize>::new();
+        for step in 0..256 {
+            let value = round * 1000 + step;
+            let index = random(s.len() + 2);
+            match random(13) {
+                0 => {
+                    v.push_back(value);
+                    s.push_back(value);
+                }
+                1 => {
+                    v.push_front(value);
+                    s.push_front(value);
+                }
+                2 => assert_eq!(v.pop_back(), s.pop_back()),
+                3 => assert_eq!(v.pop_front(), s.pop_front()),
+                4 if index <= s.len() => {
+                    v.insert(index, value);
+                    s.insert(index, value);
+                }
+                5 => {
+                    let count = random(4);
+                    v.extend(value..value + count);
+                    s.extend(value..value + count);
+                }
+                6 => {
+                    assert_eq!(v.get(index), s.get(index));
+                    if let (Some(a), Some(b)) = (v.get_mut(index), s.get_mut(index)) {
+                        *a = value;
+                        *b = value;
+                    }
+                }
+                7 => {
+                    assert_eq!(v.front(), s.front());
+                    assert_eq!(v.back(), s.back());
+                    if let (Some(a), Some(b)) = (v.front_mut(), s.front_mut()) {
+                        *a = value;
+                        *b = value;
+                    }
+                    if let (Some(a), Some(b)) = (v.back_mut(), s.back_mut()) {
+                        *a += 1;
+                        *b += 1;
+                    }
+                }
+                8 => {
+                    let v_result = catch_unwind(AssertUnwindSafe(|| v[index]));
+                    let s_result = catch_unwind(AssertUnwindSafe(|| s[index]));
+                    assert_eq!(v_result.ok(), s_result.ok());
+                }
+                9 if random(8) == 0 => {
+                    let len = random(s.len() + 1);
+                    v.truncate(len);
+                    s.truncate(len);
+                }
+                10 if random(8) == 0 => {
+                    let modulo = random(3) + 2;
+                    v.retain(|x| x % modulo != 0);
+                    s.retain(|x| x % modulo != 0);
+                }
+                11 if random(8) == 0 => {
+                    v.shrink_to(index);
+                    s.shrink_to(index);
+                }
+                _ => {}
+            }
+            assert_eq!(v.len(), s.len());
+            assert!(v.iter().eq(s.iter()), "round {round}, step {step}");
+        }
+        let collected = s.iter().copied().collect::<VecDeque<_>>();
+        assert!(collected == v);
+    }
+}
+
+#[test]
+fn custom_vecdeque_send_sync() {
+    fn assert_send<T: Send>(_: &T) {}
+    fn assert_sync<T: Sync>(_: &T) {}
+
+    let mut v: VecDeque<Vec<u8>> = (0..5).map(|i| vec![i]).collect();
+    assert_send(&v);
+    assert_sync(&v);
+    assert_send(&v.iter());
+    assert_sync(&v.iter());
+    assert_send(&v.iter_mut());
+    assert_sync(&v.iter_mut());
+    assert_send(&v.drain(..));
+    assert_sync(&v.drain(..));
+
+    let mut v: VecDeque<Vec<u8>> = (0..5).map(|i| vec![i]).collect();
+    v.pop_front();
+    v.push_back(vec![5]);
+    let v = std::thread::spawn(move || {
+        for element in &mut v {
+            element[0] += 1;
+        }
+        v
+    })
+    .join()
+    .unwrap();
+    assert!(v.iter().map(|element| element[0]).eq(2..=6));
+}
+
+#[test]
+fn custom_vecdeque_shrink_to() {
+    // the ring wraps around: 27 to 31 at the end of the buffer, 32 and 33 at its start
+    let mut v = VecDeque::with_capacity(32);
+    for i in 0..30 {
+        v.push_back(i);
+    }
+    for _ in 0..27 {
+        v.pop_front();
+    }
+    for i in 30..34 {
+        v.push_back(i);
+    }
+    assert!(v.head < v.tail);
+    assert_eq!(v.capacity(), 31);
+
+    v.shrink_to(4);
+    assert_vec!(v: 27, 28, 29, 30, 31, 32, 33; 8);
+    // the elements are kept
+    v.shrink_to(0);
+    assert_vec!(v: 27, 28, 29, 30, 31, 32, 33; 8);
+    // the capacity never grows
+    v.shrink_to(100);
+    assert_eq!(v.capacity(), 7);
+    v.push_back(34);
+    v.push_front(26);
+    assert_vec!(v: 26, 27, 28, 29, 30, 31, 32, 33, 34; 16);
+
+    // an emptied ring
+    while v.pop_back().is_some() {}
+    v.shrink_to(0);
+    assert!(v.is_empty());
+    assert_eq!(v.cap, 2);
+    v.push_front(1);
+    v.push_back(2);
+    assert_vec!(v: 1, 2; 4);
 }
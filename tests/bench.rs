@@ -1,3 +1,11 @@
+// the benchmarks predate the clippy lints of the test targets and are kept as written
+#![allow(
+    clippy::assertions_on_constants,
+    clippy::redundant_static_lifetimes,
+    clippy::unit_arg,
+    clippy::unused_io_amount
+)]
+
 use std::{hint::black_box, io::Write};
 
 use kawa::{h1, Buffer, Kawa, Kind, SliceBuffer};
@@ -34,6 +42,42 @@ Cookie: wp_ozh_wsa_visits=2; wp_ozh_wsa_visit_lasttime=xxxxxxxxxx; foo; ==bar=;
     kawa::debug_kawa(&req);
 }
 
+#[test]
+fn bench_long_values() {
+    // values of a few hundred bytes, where the width of the simd loop matters most
+    let token = "x".repeat(512);
+    let crumbs = (0..8)
+        .map(|i| format!("crumb{i}={}", "y".repeat(96)))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let request = format!(
+        "GET / HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+Authorization: Bearer {token}\r\n\
+X-Forwarded-For: {}\r\n\
+Cookie: {crumbs}\r\n\r\n",
+        ["192.168.100.200"; 24].join(", ")
+    );
+
+    let mut buffer = vec![0; 4096];
+    let mut req = Kawa::new(Kind::Request, Buffer::new(SliceBuffer(&mut buffer[..])));
+    req.storage.write_all(request.as_bytes()).expect("write");
+    req.blocks.reserve(16);
+    req.detached.jar.reserve(16);
+    for _ in 0..2_000_000 {
+        req.clear();
+        req.storage.clear();
+        req.storage.fill(request.len());
+        h1::parse(&mut req, &mut h1::NoCallbacks);
+        black_box(&req);
+        if !req.is_main_phase() {
+            kawa::debug_kawa(&req);
+            panic!();
+        }
+    }
+    kawa::debug_kawa(&req);
+}
+
 #[test]
 fn bench_short() {
     const REQ_SHORT: &'static [u8] = b"\
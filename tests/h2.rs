@@ -0,0 +1,881 @@
+use std::io::Write;
+
+use kawa::{
+    debug_kawa, h1, h2,
+    h2::frame::{FrameHeader, FrameType, END_HEADERS, END_STREAM},
+    BodySize, Buffer, Kawa, Kind, OutBlock, ParsingPhase, SliceBuffer, StatusLine, Version,
+};
+
+fn frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(9 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend_from_slice(&stream_id.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn convert_to_h1<T: kawa::AsBuffer>(kawa: &mut Kawa<T>) -> String {
+    kawa.prepare(&mut h1::BlockConverter);
+    let out = kawa.as_io_slice();
+    let mut writer = std::io::BufWriter::new(Vec::new());
+    let amount = writer.write_vectored(&out).expect("WRITE");
+    let result = String::from_utf8(writer.buffer().to_vec()).expect("UTF8");
+    kawa.consume(amount);
+    result
+}
+
+#[test]
+fn h2_request_byte_by_byte() {
+    let mut stream = Vec::new();
+    // SETTINGS on the connection stream are skipped
+    stream.extend(frame(0x4, 0, 0, &[]));
+    // :method GET, :scheme http, :path /, :authority www.example.com
+    // and a literal without indexing "cookie: a=1; b=2"
+    let mut block = b"\x82\x86\x84\x41\x0fwww.example.com\x0f\x11\x08a=1; b=2".to_vec();
+    let continuation = block.split_off(10);
+    stream.extend(frame(0x1, 0, 1, &block));
+    stream.extend(frame(0x9, 0x4, 1, &continuation));
+    // DATA with a padding of 2 bytes then an empty DATA with END_STREAM
+    stream.extend(frame(0x0, 0x8, 1, b"\x02hello\0\0"));
+    stream.extend(frame(0x0, 0x1, 1, b""));
+
+    let mut buffer = vec![0; 4096];
+    let mut req = Kawa::new(Kind::Request, Buffer::new(SliceBuffer(&mut buffer[..])));
+    let mut decoder = h2::hpack::Decoder::new();
+    for byte in &stream {
+        assert!(!req.is_terminated());
+        req.storage.write_all(&[*byte]).expect("WRITE");
+        h2::parse(&mut req, &mut decoder, &mut h2::NoCallbacks);
+        assert!(!req.is_error());
+    }
+    debug_kawa(&req);
+    assert!(req.is_terminated());
+    assert!(req.is_streaming());
+    assert!(req.storage.unparsed_data().is_empty());
+    assert_eq!(req.detached.jar.len(), 2);
+    match &req.detached.status_line {
+        StatusLine::Request {
+            version: Version::V20,
+            method,
+            authority,
+            path,
+            ..
+        } => {
+            let buf = req.storage.buffer();
+            assert_eq!(method.data(buf), b"GET");
+            assert_eq!(authority.data(buf), b"www.example.com");
+            assert_eq!(path.data(buf), b"/");
+        }
+        _ => panic!("expected a request status line"),
+    }
+
+    let h1 = convert_to_h1(&mut req);
+    assert_eq!(
+        h1,
+        "GET / HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+Cookie: a=1; b=2\r\n\
+transfer-encoding: chunked\r\n\r\n\
+5\r\nhello\r\n\
+0\r\n\r\n"
+    );
+}
+
+#[test]
+fn h2_response_with_content_length() {
+    // :status 200, content-length: 3
+    let mut stream = frame(0x1, 0x4, 1, b"\x88\x0f\x0d\x013");
+    stream.extend(frame(0x0, 0x0, 1, b"AB"));
+    stream.extend(frame(0x0, 0x1, 1, b"C"));
+
+    let mut buffer = vec![0; 4096];
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(SliceBuffer(&mut buffer[..])));
+    let mut decoder = h2::hpack::Decoder::new();
+    let (first, second) = stream.split_at(14);
+    resp.storage.write_all(first).expect("WRITE");
+    h2::parse(&mut resp, &mut decoder, &mut h2::NoCallbacks);
+    assert!(!resp.is_error());
+    assert_eq!(resp.parsing_phase, ParsingPhase::Body);
+    resp.storage.write_all(second).expect("WRITE");
+    h2::parse(&mut resp, &mut decoder, &mut h2::NoCallbacks);
+    debug_kawa(&resp);
+    assert!(resp.is_terminated());
+    assert_eq!(resp.body_size, BodySize::Length(3));
+
+    let h1 = convert_to_h1(&mut resp);
+    assert_eq!(h1, "HTTP/1.1 200 \r\ncontent-length: 3\r\n\r\nABC");
+}
+
+#[test]
+fn h2_invalid_frames() {
+    let mut buffer = vec![0; 4096];
+    let mut decoder = h2::hpack::Decoder::new();
+
+    // HEADERS on the connection stream
+    let mut req = Kawa::new(Kind::Request, Buffer::new(SliceBuffer(&mut buffer[..])));
+    req.storage
+        .write_all(&frame(0x1, 0x4, 0, b"\x82\x86\x84"))
+        .expect("WRITE");
+    h2::parse(&mut req, &mut decoder, &mut h2::NoCallbacks);
+    assert!(req.is_error());
+
+    // DATA longer than the announced content-length
+    req.clear();
+    req.storage
+        .write_all(&frame(0x1, 0x4, 1, b"\x82\x86\x84\x0f\x0d\x011"))
+        .expect("WRITE");
+    req.storage
+        .write_all(&frame(0x0, 0x1, 1, b"AB"))
+        .expect("WRITE");
+    h2::parse(&mut req, &mut decoder, &mut h2::NoCallbacks);
+    assert!(req.is_error());
+}
+
+#[test]
+fn h2_converter_huffman() {
+    const REQUEST: &[u8] = b"\
+GET /index.html HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+User-Agent: kawa\r\n\
+Connection: keep-alive\r\n\
+Cookie: a=1; b=2\r\n\
+Content-Length: 5\r\n\r\nhello";
+
+    let mut sizes = Vec::new();
+    for huffman in [false, true] {
+        let mut req = Kawa::new(Kind::Request, Buffer::new(vec![0; 4096]));
+        req.storage.write_all(REQUEST).expect("WRITE");
+        h1::parse(&mut req, &mut h1::NoCallbacks);
+        req.prepare(&mut h2::BlockConverter::with_huffman(huffman));
+
+        let frames = frames(&req);
+        let (header, header_block) = &frames[0];
+        assert_eq!(header.kind, FrameType::Headers);
+        assert_eq!(header.flags, END_HEADERS);
+        let fields = decode_fields(&mut h2::hpack::Decoder::new(), header_block);
+        let fields = fields
+            .iter()
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":authority", "www.example.com"),
+                (":path", "/index.html"),
+                ("user-agent", "kawa"),
+                ("cookie", "a=1"),
+                ("cookie", "b=2"),
+                ("content-length", "5"),
+            ]
+        );
+        sizes.push(header_block.len());
+
+        // DATA payload ending the stream
+        let (header, data) = &frames[1];
+        assert_eq!((header.kind, header.flags), (FrameType::Data, END_STREAM));
+        assert_eq!(data, b"hello");
+        assert_eq!(frames.len(), 2);
+    }
+    assert!(sizes[1] < sizes[0]);
+}
+
+fn decode_fields(decoder: &mut h2::hpack::Decoder, header_block: &[u8]) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    decoder
+        .decode(header_block, header_block, |key, val| {
+            fields.push((
+                String::from_utf8(key.data(header_block).to_vec()).expect("UTF8"),
+                String::from_utf8(val.data(header_block).to_vec()).expect("UTF8"),
+            ))
+        })
+        .expect("DECODE");
+    fields
+}
+
+fn convert_header_block(converter: &mut h2::BlockConverter, request: &[u8]) -> Vec<u8> {
+    let mut req = Kawa::new(Kind::Request, Buffer::new(vec![0; 4096]));
+    req.storage.write_all(request).expect("WRITE");
+    h1::parse(&mut req, &mut h1::NoCallbacks);
+    req.prepare(converter);
+    frame_payloads(&req).swap_remove(0)
+}
+
+#[test]
+fn h2_converter_dynamic_table() {
+    const REQUEST: &[u8] = b"\
+GET /index.html HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0\r\n\
+Cookie: session=0123456789abcdef\r\n\r\n";
+
+    // one converter and one decoder for the whole connection
+    let mut converter = h2::BlockConverter::default();
+    let mut decoder = h2::hpack::Decoder::new();
+
+    let first = convert_header_block(&mut converter, REQUEST);
+    let second = convert_header_block(&mut converter, REQUEST);
+    println!("{} -> {}", first.len(), second.len());
+    // every field is indexed the second time
+    assert_eq!(second.len(), 6);
+    let expected = decode_fields(&mut decoder, &first);
+    assert_eq!(decode_fields(&mut decoder, &second), expected);
+    // :method, :scheme and :path are found in the static table
+    assert_eq!(converter.encoder.table.len(), 3);
+
+    // shrinking the table evicts the entries and emits a size update
+    converter.set_max_table_size(0);
+    converter.set_max_table_size(256);
+    let third = convert_header_block(&mut converter, REQUEST);
+    assert_eq!(&third[..2], &[0x20, 0x3f]);
+    assert_eq!(decode_fields(&mut decoder, &third), expected);
+    assert_eq!(decoder.table.max_size(), 256);
+    assert!(converter.encoder.table.size() <= 256);
+}
+
+fn frames<T: kawa::AsBuffer>(kawa: &Kawa<T>) -> Vec<(FrameHeader, Vec<u8>)> {
+    let buf = kawa.storage.buffer();
+    let mut bytes = Vec::new();
+    for block in kawa.out.iter() {
+        if let OutBlock::Store(store) = block {
+            bytes.extend(store.data(buf));
+        }
+    }
+    let mut frames = Vec::new();
+    let mut i = &bytes[..];
+    while !i.is_empty() {
+        let (rest, header) = h2::frame::frame_header(i).expect("FRAME HEADER");
+        assert!(rest.len() >= header.length, "truncated frame");
+        let (payload, rest) = rest.split_at(header.length);
+        frames.push((header, payload.to_vec()));
+        i = rest;
+    }
+    frames
+}
+
+fn frame_sizes<T: kawa::AsBuffer>(kawa: &Kawa<T>) -> Vec<usize> {
+    frames(kawa)
+        .iter()
+        .map(|(header, _)| header.length)
+        .collect()
+}
+
+fn frame_payloads<T: kawa::AsBuffer>(kawa: &Kawa<T>) -> Vec<Vec<u8>> {
+    frames(kawa)
+        .into_iter()
+        .map(|(_, payload)| payload)
+        .collect()
+}
+
+fn convert_body_frames(body_len: usize) -> Vec<usize> {
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; body_len + 128]));
+    write!(
+        resp.storage,
+        "HTTP/1.1 200 OK\r\nContent-Length: {body_len}\r\n\r\n"
+    )
+    .expect("WRITE");
+    resp.storage
+        .write_all(&vec![b'x'; body_len])
+        .expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    assert!(resp.is_terminated());
+    resp.prepare(&mut h2::BlockConverter::default());
+    // skip the header block
+    frame_sizes(&resp)[1..].to_vec()
+}
+
+#[test]
+fn h2_data_frame_split() {
+    let sizes = convert_body_frames(100 * 1024);
+    assert_eq!(sizes, [16384, 16384, 16384, 16384, 16384, 16384, 4096]);
+
+    // a chunk exactly as large as the limit is not split
+    let sizes = convert_body_frames(16384);
+    assert_eq!(sizes, [16384]);
+
+    // each frame is delimited and only the last one ends the stream
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 64 * 1024]));
+    write!(
+        resp.storage,
+        "HTTP/1.1 200 OK\r\nContent-Length: 40960\r\n\r\n"
+    )
+    .expect("WRITE");
+    resp.storage.write_all(&[b'x'; 40960]).expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    resp.prepare(&mut h2::BlockConverter::default());
+    let kinds = frames(&resp)
+        .iter()
+        .map(|(header, _)| (header.kind, header.flags, header.length))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds[1..],
+        [
+            (FrameType::Data, 0, 16384),
+            (FrameType::Data, 0, 16384),
+            (FrameType::Data, END_STREAM, 8192),
+        ]
+    );
+    let mut segments = 0;
+    while !resp.out.is_empty() {
+        let amount = resp.as_io_slice().iter().map(|slice| slice.len()).sum();
+        resp.consume(amount);
+        segments += 1;
+    }
+    // the HEADERS frame and three DATA frames
+    assert_eq!(segments, 4);
+
+    // the end of the stream is not known yet when the last chunk is converted
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+    resp.storage
+        .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n")
+        .expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    let mut converter = h2::BlockConverter::default();
+    resp.prepare(&mut converter);
+    resp.storage.write_all(b"0\r\n\r\n").expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    assert!(resp.is_terminated());
+    resp.prepare(&mut converter);
+    let kinds = frames(&resp)
+        .iter()
+        .map(|(header, _)| (header.kind, header.flags, header.length))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds[1..],
+        [(FrameType::Data, 0, 4), (FrameType::Data, END_STREAM, 0)]
+    );
+}
+
+#[test]
+fn h2_continuation_frames() {
+    let cookie = "c".repeat(64 * 1024);
+    let request = format!("GET / HTTP/1.1\r\nHost: example.com\r\nCookie: a=1; b={cookie}\r\n\r\n");
+    let mut req = Kawa::new(Kind::Request, Buffer::new(vec![0; 128 * 1024]));
+    req.max_header_line = 128 * 1024;
+    req.storage.write_all(request.as_bytes()).expect("WRITE");
+    h1::parse(&mut req, &mut h1::NoCallbacks);
+    assert!(req.is_main_phase());
+    req.prepare(&mut h2::BlockConverter::default());
+
+    // a HEADERS frame followed by CONTINUATION frames, only the last one ends the headers, the
+    // request has no body so the HEADERS frame ends the stream
+    let frames = frames(&req);
+    let headers = frames
+        .iter()
+        .map(|(header, payload)| (header.kind, header.flags, payload.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        headers,
+        [
+            (FrameType::Headers, END_STREAM, 16384),
+            (FrameType::Continuation, 0, 16384),
+            (FrameType::Continuation, 0, 16384),
+            (FrameType::Continuation, 0, 16384),
+            (FrameType::Continuation, END_HEADERS, 28),
+        ]
+    );
+
+    let header_block = frames
+        .into_iter()
+        .flat_map(|(_, payload)| payload)
+        .collect::<Vec<_>>();
+    let fields = decode_fields(&mut h2::hpack::Decoder::new(), &header_block);
+    assert_eq!(
+        fields,
+        [
+            (":method".to_string(), "GET".to_string()),
+            (":scheme".to_string(), "http".to_string()),
+            (":authority".to_string(), "example.com".to_string()),
+            (":path".to_string(), "/".to_string()),
+            ("cookie".to_string(), "a=1".to_string()),
+            ("cookie".to_string(), format!("b={cookie}")),
+        ]
+    );
+}
+
+fn h2_request_with_cookies(cookies: &[&str]) -> Kawa<Vec<u8>> {
+    let mut encoder = h2::hpack::Encoder::default();
+    let mut block = Vec::new();
+    encoder.encode(b":method", b"GET", &mut block);
+    encoder.encode(b":scheme", b"http", &mut block);
+    encoder.encode(b":authority", b"example.com", &mut block);
+    encoder.encode(b":path", b"/", &mut block);
+    for cookie in cookies {
+        encoder.encode(b"cookie", cookie.as_bytes(), &mut block);
+    }
+    let mut req = Kawa::new(Kind::Request, Buffer::new(vec![0; 4096]));
+    req.storage
+        .write_all(&frame(0x1, 0x5, 1, &block))
+        .expect("WRITE");
+    h2::parse(
+        &mut req,
+        &mut h2::hpack::Decoder::new(),
+        &mut h2::NoCallbacks,
+    );
+    assert!(req.is_terminated());
+    req
+}
+
+#[test]
+fn h2_cookies_to_h1() {
+    const COOKIES: &[&str] = &["a=1; b=2", "c=3", "flag"];
+
+    let mut req = h2_request_with_cookies(COOKIES);
+    assert_eq!(req.detached.jar.len(), 4);
+    assert_eq!(
+        convert_to_h1(&mut req),
+        "GET / HTTP/1.1\r\nHost: example.com\r\nCookie: a=1; b=2; c=3; flag\r\n\r\n"
+    );
+
+    // no stray separator whichever crumbs are elided
+    let mut req = h2_request_with_cookies(COOKIES);
+    req.detached.jar[0].elide();
+    req.detached.jar[2].elide();
+    assert_eq!(
+        convert_to_h1(&mut req),
+        "GET / HTTP/1.1\r\nHost: example.com\r\nCookie: b=2; flag\r\n\r\n"
+    );
+
+    let mut req = h2_request_with_cookies(COOKIES);
+    req.detached.jar[1].elide();
+    req.detached.jar[3].elide();
+    assert_eq!(
+        convert_to_h1(&mut req),
+        "GET / HTTP/1.1\r\nHost: example.com\r\nCookie: a=1; c=3\r\n\r\n"
+    );
+
+    // a crumb with an empty name keeps its "=", a crumb without "=" doesn't get one
+    let mut req = h2_request_with_cookies(&["a=1; ==bar=; =x; foo"]);
+    assert_eq!(
+        convert_to_h1(&mut req),
+        "GET / HTTP/1.1\r\nHost: example.com\r\nCookie: a=1; ==bar=; =x; foo\r\n\r\n"
+    );
+
+    // the header is omitted when every crumb is elided
+    let mut req = h2_request_with_cookies(COOKIES);
+    for cookie in req.detached.jar.iter_mut() {
+        cookie.elide();
+    }
+    assert_eq!(
+        convert_to_h1(&mut req),
+        "GET / HTTP/1.1\r\nHost: example.com\r\n\r\n"
+    );
+}
+
+#[test]
+fn grpc_message_framing() {
+    let mut body = Vec::new();
+    for message in [&b"abc"[..], b"", b"wxyz"] {
+        body.push(0);
+        body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        body.extend_from_slice(message);
+    }
+    // chunk boundaries fall inside the prefixes and the payloads of the messages
+    let mut response =
+        b"HTTP/1.1 200 OK\r\nContent-Type: application/grpc\r\nTransfer-Encoding: chunked\r\n\r\n"
+            .to_vec();
+    for chunk in [&body[..2], &body[2..10], &body[10..16], &body[16..]] {
+        write!(response, "{:x}\r\n", chunk.len()).expect("WRITE");
+        response.extend_from_slice(chunk);
+        response.extend_from_slice(b"\r\n");
+    }
+    response.extend_from_slice(b"0\r\n\r\n");
+
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+    resp.storage.write_all(&response).expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    assert!(resp.is_terminated());
+    let mut converter = h2::GrpcBlockConverter::default();
+    resp.prepare(&mut converter);
+    assert!(!converter.has_partial_message());
+
+    // header block, the three messages and the END_STREAM frame
+    let frames = frame_payloads(&resp);
+    assert_eq!(frames.len(), 5);
+    assert_eq!(&frames[1..], [&body[..8], &body[8..13], &body[13..], &[]]);
+
+    // a message larger than max_frame_size spans several DATA frames
+    let mut body = vec![0];
+    body.extend_from_slice(&20000u32.to_be_bytes());
+    body.extend_from_slice(&[b'x'; 20000]);
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 32 * 1024]));
+    write!(
+        resp.storage,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .expect("WRITE");
+    resp.storage.write_all(&body).expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    let mut converter = h2::GrpcBlockConverter::default();
+    resp.prepare(&mut converter);
+    assert_eq!(frame_sizes(&resp)[1..], [16384, 20005 - 16384, 0]);
+    let delimiters = resp
+        .out
+        .iter()
+        .filter(|block| matches!(block, OutBlock::Delimiter))
+        .count();
+    // header block, the message and the END_STREAM frame
+    assert_eq!(delimiters, 3);
+}
+
+#[test]
+fn h2_stream_id() {
+    const REQUEST: &[u8] = b"POST / HTTP/1.1\r\nHost: example.com\r\nContent-Length: 2\r\n\r\nok";
+
+    let mut converter = h2::BlockConverter::new(3);
+    for stream_id in [3, 5] {
+        converter.set_stream_id(stream_id);
+        let mut req = Kawa::new(Kind::Request, Buffer::new(vec![0; 4096]));
+        req.storage.write_all(REQUEST).expect("WRITE");
+        h1::parse(&mut req, &mut h1::NoCallbacks);
+        req.prepare(&mut converter);
+        assert!(!req.is_error());
+        let frames = frames(&req);
+        assert_eq!(frames.len(), 2);
+        assert!(frames
+            .iter()
+            .all(|(header, _)| header.stream_id == stream_id));
+    }
+
+    // requests can not be sent on server initiated streams
+    converter.set_stream_id(2);
+    let mut req = Kawa::new(Kind::Request, Buffer::new(vec![0; 4096]));
+    req.storage.write_all(REQUEST).expect("WRITE");
+    h1::parse(&mut req, &mut h1::NoCallbacks);
+    req.prepare(&mut converter);
+    assert!(req.is_error());
+    assert!(req.out.is_empty());
+    assert_eq!(converter.stream_id(), 2);
+}
+
+#[test]
+fn h2c_preface() {
+    let settings = h2::Settings {
+        header_table_size: 0,
+        max_frame_size: 1 << 20,
+        ..Default::default()
+    };
+    let mut req = Kawa::new(Kind::Request, Buffer::new(Vec::new()));
+    for store in h2::preface(&settings) {
+        req.push_out(store);
+    }
+    let preface = req
+        .as_io_slice()
+        .iter()
+        .flat_map(|s| s.to_vec())
+        .collect::<Vec<_>>();
+
+    let (settings_frame, magic) = (&preface[24..], &preface[..24]);
+    assert_eq!(magic, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+    assert_eq!(
+        settings_frame,
+        frame(
+            0x4,
+            0,
+            0,
+            &[
+                0x00, 0x01, 0x00, 0x00, 0x00, 0x00, // HEADER_TABLE_SIZE 0
+                0x00, 0x04, 0x00, 0x00, 0xff, 0xff, // INITIAL_WINDOW_SIZE 65535
+                0x00, 0x05, 0x00, 0x10, 0x00, 0x00, // MAX_FRAME_SIZE 1048576
+            ]
+        )
+    );
+    let (payload, header) = h2::frame::frame_header(settings_frame).expect("FRAME");
+    assert_eq!(header.length, payload.len());
+    assert_eq!(header.kind, h2::frame::FrameType::Settings);
+}
+
+fn data_sent<T: kawa::AsBuffer>(kawa: &Kawa<T>) -> Vec<u8> {
+    frames(kawa)
+        .into_iter()
+        .filter(|(header, _)| header.kind == FrameType::Data)
+        .flat_map(|(_, payload)| payload)
+        .collect()
+}
+
+#[test]
+fn h2_flow_control() {
+    let body = (0..100 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 128 * 1024]));
+    write!(
+        resp.storage,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .expect("WRITE");
+    resp.storage.write_all(&body).expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    assert!(resp.is_terminated());
+
+    let mut converter = h2::BlockConverter::default();
+    converter.window = h2::frame::DEFAULT_INITIAL_WINDOW_SIZE;
+    assert!(resp.prepare(&mut converter).paused);
+    assert_eq!(data_sent(&resp).len(), 65535);
+    assert_eq!(converter.window, 0);
+    // nothing is sent without window
+    assert!(resp.prepare(&mut converter).paused);
+    assert_eq!(data_sent(&resp).len(), 65535);
+
+    let mut grants = 0;
+    while !resp.blocks.is_empty() {
+        let before = data_sent(&resp).len();
+        converter.grant(10000);
+        resp.prepare(&mut converter);
+        assert!(data_sent(&resp).len() - before <= 10000);
+        grants += 1;
+    }
+    assert_eq!(grants, 4);
+    assert_eq!(data_sent(&resp), body);
+    let frames = frames(&resp);
+    let (header, _) = frames.last().unwrap();
+    assert_eq!((header.kind, header.flags), (FrameType::Data, END_STREAM));
+
+    // messages only start with enough window for their prefix
+    let mut body = Vec::new();
+    for message in [&b"0123456789"[..], b"abc"] {
+        body.push(0);
+        body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        body.extend_from_slice(message);
+    }
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+    write!(
+        resp.storage,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .expect("WRITE");
+    resp.storage.write_all(&body).expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    let mut converter = h2::GrpcBlockConverter::default();
+    converter.inner.window = 12;
+    assert!(resp.prepare(&mut converter).paused);
+    assert_eq!(data_sent(&resp), &body[..12]);
+    converter.inner.grant(4);
+    assert!(resp.prepare(&mut converter).paused);
+    assert_eq!(data_sent(&resp), &body[..15]);
+    converter.inner.grant(100);
+    assert!(!resp.prepare(&mut converter).paused);
+    assert!(!converter.has_partial_message());
+    assert_eq!(data_sent(&resp), body);
+    assert_eq!(converter.inner.window, 1 + 100 - 8);
+}
+
+#[test]
+fn h2_trailers() {
+    const RESPONSE: &[u8] =
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTrailer: Foo\r\n\r\n\
+4\r\nWiki\r\n5\r\npedia\r\n0\r\nFoo: bar\r\n\r\n";
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+    resp.storage.write_all(RESPONSE).expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    assert!(resp.is_terminated());
+    resp.prepare(&mut h2::BlockConverter::default());
+
+    // HEADERS, the DATA of each chunk, then the trailers in a HEADERS frame ending the stream
+    let mut decoder = h2::hpack::Decoder::new();
+    let sent = frames(&resp);
+    let kinds = sent
+        .iter()
+        .map(|(header, _)| (header.kind, header.flags))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            (FrameType::Headers, END_HEADERS),
+            (FrameType::Data, 0),
+            (FrameType::Data, 0),
+            (FrameType::Headers, END_HEADERS | END_STREAM),
+        ]
+    );
+    assert_eq!(
+        decode_fields(&mut decoder, &sent[0].1),
+        [(":status".to_string(), "200".to_string())]
+    );
+    assert_eq!(data_sent(&resp), b"Wikipedia");
+    assert_eq!(
+        decode_fields(&mut decoder, &sent[3].1),
+        [("foo".to_string(), "bar".to_string())]
+    );
+
+    // without trailers, the last DATA frame ends the stream
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+    resp.storage
+        .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n0\r\n\r\n")
+        .expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    assert!(resp.is_terminated());
+    resp.prepare(&mut h2::BlockConverter::default());
+    let (header, payload) = frames(&resp).pop().unwrap();
+    assert_eq!((header.kind, header.flags), (FrameType::Data, END_STREAM));
+    assert_eq!(payload, b"Wiki");
+}
+
+#[derive(Default)]
+struct RecordingFrames {
+    events: Vec<String>,
+}
+
+impl h2::FrameCallbacks for RecordingFrames {
+    fn on_settings(&mut self, settings: &[(u16, u32)], ack: bool) {
+        self.events.push(format!("settings {settings:?} {ack}"));
+    }
+    fn on_ping(&mut self, data: [u8; 8], ack: bool) {
+        self.events.push(format!("ping {data:?} {ack}"));
+    }
+    fn on_window_update(&mut self, stream_id: u32, increment: u32) {
+        self.events
+            .push(format!("window update {stream_id} {increment}"));
+    }
+    fn on_goaway(&mut self, last_stream_id: u32, error_code: u32) {
+        self.events
+            .push(format!("goaway {last_stream_id} {error_code}"));
+    }
+}
+
+#[test]
+fn h2_connection_frames() {
+    // :status 200, content-length: 2
+    let mut stream = frame(
+        0x4,
+        0,
+        0,
+        b"\x00\x04\x00\x01\x00\x00\x00\x05\x00\x00\x40\x00",
+    );
+    stream.extend(frame(0x1, 0x4, 1, b"\x88\x0f\x0d\x012"));
+    stream.extend(frame(0x6, 0, 0, b"abcdefgh"));
+    stream.extend(frame(0x8, 0, 0, &1000u32.to_be_bytes()));
+    // unknown frame types are skipped
+    stream.extend(frame(0xfa, 0xff, 1, b"unknown"));
+    stream.extend(frame(0x0, 0, 1, b"o"));
+    stream.extend(frame(0x8, 0, 1, &2000u32.to_be_bytes()));
+    stream.extend(frame(0x4, 0x1, 0, b""));
+    stream.extend(frame(0x7, 0, 0, b"\x00\x00\x00\x01\x00\x00\x00\x00debug"));
+    stream.extend(frame(0x0, 0x1, 1, b"k"));
+
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+    let mut frames = RecordingFrames::default();
+    resp.storage.write_all(&stream).expect("WRITE");
+    h2::parse_with_frames(
+        &mut resp,
+        &mut h2::hpack::Decoder::new(),
+        &mut h2::NoCallbacks,
+        &mut frames,
+    );
+    assert!(resp.is_terminated());
+    assert!(resp.storage.unparsed_data().is_empty());
+    assert_eq!(
+        frames.events,
+        [
+            "settings [(4, 65536), (5, 16384)] false",
+            "ping [97, 98, 99, 100, 101, 102, 103, 104] false",
+            "window update 0 1000",
+            "window update 1 2000",
+            "settings [] true",
+            "goaway 1 0",
+        ]
+    );
+    assert_eq!(
+        convert_to_h1(&mut resp),
+        "HTTP/1.1 200 \r\ncontent-length: 2\r\n\r\nok"
+    );
+
+    // connection frames must be sent on the stream 0
+    for invalid in [
+        frame(0x4, 0, 1, b""),
+        frame(0x4, 0, 0, b"\x00\x04"),
+        frame(0x6, 0, 0, b"abc"),
+        frame(0x8, 0, 0, b"\x00"),
+    ] {
+        let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+        resp.storage.write_all(&invalid).expect("WRITE");
+        h2::parse(
+            &mut resp,
+            &mut h2::hpack::Decoder::new(),
+            &mut h2::NoCallbacks,
+        );
+        assert!(resp.is_error());
+    }
+}
+
+#[test]
+fn h2_frame_headers() {
+    let mut response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    write!(response, "{:x}\r\n", 20000).expect("WRITE");
+    response.extend_from_slice(&[b'x'; 20000]);
+    response.extend_from_slice(b"\r\n5\r\npedia\r\n0\r\nFoo: bar\r\n\r\n");
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 32 * 1024]));
+    resp.storage.write_all(&response).expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    assert!(resp.is_terminated());
+    resp.prepare(&mut h2::BlockConverter::new(7));
+
+    // each segment delimited in the out vector holds exactly one frame
+    let mut segments = Vec::new();
+    while !resp.out.is_empty() {
+        let segment = resp
+            .as_io_slice()
+            .iter()
+            .flat_map(|slice| slice.to_vec())
+            .collect::<Vec<_>>();
+        resp.consume(segment.len());
+        let (payload, header) = h2::frame::frame_header(&segment).expect("FRAME HEADER");
+        assert_eq!(header.length, payload.len());
+        assert_eq!(header.stream_id, 7);
+        segments.push((header.kind, header.flags, header.length));
+    }
+    assert_eq!(
+        segments,
+        [
+            // :status 200 is indexed in the static table
+            (FrameType::Headers, END_HEADERS, 1),
+            (FrameType::Data, 0, 16384),
+            (FrameType::Data, 0, 20000 - 16384),
+            (FrameType::Data, 0, 5),
+            (FrameType::Headers, END_HEADERS | END_STREAM, 9),
+        ]
+    );
+}
+
+#[test]
+fn h2_max_header_list_size() {
+    // :method GET (42), :scheme http (43), :authority example.com (53), :path / (38) and
+    // x-a: 1234 (39), the Host header is not sent
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: example.com\r\nX-A: 1234\r\n\r\n";
+    for (limit, fits) in [(215, true), (214, false)] {
+        let mut req = Kawa::new(Kind::Request, Buffer::new(vec![0; 4096]));
+        req.storage.write_all(REQUEST).expect("WRITE");
+        h1::parse(&mut req, &mut h1::NoCallbacks);
+        let mut converter = h2::BlockConverter::default();
+        converter.max_header_list_size = limit;
+        req.prepare(&mut converter);
+        assert_eq!(req.is_error(), !fits);
+        if fits {
+            assert_eq!(frame_sizes(&req).len(), 1);
+        } else {
+            // the section is rejected before being encoded
+            assert!(req.out.is_empty());
+            assert!(converter.encoder.table.is_empty());
+        }
+    }
+
+    // a section converted before being entirely parsed is checked field by field,
+    // :status 200 (42) and x-a: 1234 (39)
+    let mut resp = Kawa::new(Kind::Response, Buffer::new(vec![0; 4096]));
+    resp.storage
+        .write_all(b"HTTP/1.1 200 OK\r\nX-A: 1234\r\n")
+        .expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    let mut converter = h2::BlockConverter::default();
+    converter.max_header_list_size = 81;
+    resp.prepare(&mut converter);
+    assert!(!resp.is_error());
+    resp.storage.write_all(b"X-B: 1\r\n\r\n").expect("WRITE");
+    h1::parse(&mut resp, &mut h1::NoCallbacks);
+    resp.prepare(&mut converter);
+    assert!(resp.is_error());
+    assert!(resp.out.is_empty());
+}
@@ -1,6 +1,12 @@
+// the test helpers predate the clippy lints of the test targets and are kept as written
+#![allow(clippy::let_and_return)]
+
 use std::io::Write;
 
-use kawa::{debug_kawa, h1, h2, AsBuffer, BlockConverter, Buffer, Kawa, Kind, SliceBuffer};
+use kawa::{
+    debug_kawa, h1, h2, AsBuffer, Block, BlockConverter, Buffer, Kawa, Kind, PrepareOutcome,
+    SliceBuffer,
+};
 
 fn test_with_converter<T: AsBuffer, C: BlockConverter<T>>(
     kind: Kind,
@@ -23,7 +29,7 @@ fn test_with_converter<T: AsBuffer, C: BlockConverter<T>>(
     println!("{out:?}");
     let mut writer = std::io::BufWriter::new(Vec::new());
     let amount = writer.write_vectored(&out).expect("WRITE");
-    let result = unsafe { std::str::from_utf8_unchecked(writer.buffer()) };
+    let result = String::from_utf8_lossy(writer.buffer());
     println!("===============================\n{result}\n===============================");
 
     let buffer = unsafe { std::str::from_utf8_unchecked(kawa.storage.used()) };
@@ -38,7 +44,7 @@ fn test<T: AsBuffer>(kind: Kind, storage: T, fragment: &[u8]) -> T {
     let buffer = Buffer::new(storage);
     let storage = test_with_converter(kind, buffer, fragment, &mut h1::BlockConverter);
     let buffer = Buffer::new(storage);
-    let storage = test_with_converter(kind, buffer, fragment, &mut h2::BlockConverter);
+    let storage = test_with_converter(kind, buffer, fragment, &mut h2::BlockConverter::default());
     storage
 }
 
@@ -71,7 +77,7 @@ fn test_partial_with_converter<T: AsBuffer, C: BlockConverter<T>>(
         println!("{amount:?}");
         kawa.consume(amount);
 
-        let result = unsafe { std::str::from_utf8_unchecked(writer.buffer()) };
+        let result = String::from_utf8_lossy(writer.buffer());
         println!("===============================\n{result}\n===============================");
     }
     debug_kawa(&kawa);
@@ -88,7 +94,7 @@ fn test_partial<T: AsBuffer>(kind: Kind, storage: T, fragments: Vec<&[u8]>) -> T
         kind,
         Buffer::new(storage),
         fragments,
-        &mut h2::BlockConverter,
+        &mut h2::BlockConverter::default(),
     );
     storage
 }
@@ -160,3 +166,158 @@ Foo: bar\r
         ],
     );
 }
+
+fn convert_all<T: AsBuffer, C: BlockConverter<T>>(
+    kawa: &mut Kawa<T>,
+    converter: &mut C,
+) -> (Vec<u8>, usize) {
+    let mut writer = Vec::new();
+    let mut prepare_calls = 0;
+    while !kawa.is_completed() {
+        kawa.prepare(converter);
+        prepare_calls += 1;
+        while !kawa.out.is_empty() {
+            let out = kawa.as_io_slice();
+            let amount = writer.write_vectored(&out).expect("WRITE");
+            kawa.consume(amount);
+        }
+    }
+    (writer, prepare_calls)
+}
+
+#[test]
+fn h2_frame_budget() {
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r
+Transfer-Encoding: chunked\r
+\r
+4\r
+Wiki\r
+5\r
+pedia\r
+0\r
+\r
+";
+    let mut kawa = Kawa::new(Kind::Response, Buffer::new(vec![0; 512]));
+    kawa.storage.write_all(RESPONSE).expect("WRITE");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    let mut budget_kawa = Kawa::new(Kind::Response, Buffer::new(vec![0; 512]));
+    budget_kawa.storage.write_all(RESPONSE).expect("WRITE");
+    h1::parse(&mut budget_kawa, &mut h1::NoCallbacks);
+
+    let (expected, prepare_calls) = convert_all(&mut kawa, &mut h2::BlockConverter::default());
+    assert_eq!(prepare_calls, 1);
+
+    let mut converter = h2::BlockConverter::with_max_frames(1);
+    budget_kawa.prepare(&mut converter);
+    debug_kawa(&budget_kawa);
+    // only the header frame is converted, the body is left for the next prepare calls
+    assert!(!budget_kawa.blocks.is_empty());
+    let (result, prepare_calls) = convert_all(&mut budget_kawa, &mut converter);
+    // one prepare call for each data frame, the last one ending the stream, and one for the
+    // remaining flags
+    assert_eq!(prepare_calls, 3);
+    assert_eq!(result, expected);
+}
+
+/// Wraps the H1 converter and stops the conversion every `pause_every` blocks, handing back the
+/// block it refused.
+struct PausingConverter {
+    pause_every: usize,
+    converted: usize,
+}
+
+impl<T: AsBuffer> BlockConverter<T> for PausingConverter {
+    fn initialize(&mut self, _kawa: &mut Kawa<T>) {
+        self.converted = 0;
+    }
+    fn call(&mut self, block: Block, kawa: &mut Kawa<T>) -> bool {
+        if self.converted == self.pause_every {
+            kawa.push_block_front(block);
+            return false;
+        }
+        self.converted += 1;
+        h1::BlockConverter.call(block, kawa)
+    }
+}
+
+#[test]
+fn paused_prepare_keeps_blocks() {
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r
+Transfer-Encoding: chunked\r
+\r
+4\r
+Wiki\r
+5\r
+pedia\r
+E\r
+ in\r
+\r
+chunks.\r
+0\r
+\r
+";
+    let mut kawa = Kawa::new(Kind::Response, Buffer::new(vec![0; 512]));
+    kawa.storage.write_all(RESPONSE).expect("WRITE");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    assert!(kawa.is_terminated());
+
+    let mut converter = PausingConverter {
+        pause_every: 4,
+        converted: 0,
+    };
+    // pauses halfway through the chunked body
+    let outcome = kawa.prepare(&mut converter);
+    assert_eq!(
+        outcome,
+        PrepareOutcome {
+            converted: 4,
+            paused: true
+        }
+    );
+    assert!(!kawa.blocks.is_empty());
+    let (result, prepare_calls) = convert_all(&mut kawa, &mut converter);
+    assert!(prepare_calls > 1);
+    // the first prepare call output is still in out, it is written first by convert_all
+    assert_eq!(
+        std::str::from_utf8(&result).expect("UTF8"),
+        std::str::from_utf8(RESPONSE).expect("UTF8")
+    );
+}
+
+#[test]
+fn prepare_outcome() {
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: www.example.com\r\nX-A: 1\r\nX-B: 2\r\n\r\n";
+    let mut kawa = Kawa::new(Kind::Request, Buffer::new(vec![0; 512]));
+    kawa.storage.write_all(REQUEST).expect("WRITE");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    // StatusLine, 3 Headers (Host being elided) and Flags
+    let total = kawa.blocks.len();
+    assert_eq!(total, 5);
+
+    let mut converter = PausingConverter {
+        pause_every: 2,
+        converted: 0,
+    };
+    let mut converted = 0;
+    for _ in 0..2 {
+        let outcome = kawa.prepare(&mut converter);
+        assert_eq!(
+            outcome,
+            PrepareOutcome {
+                converted: 2,
+                paused: true
+            }
+        );
+        converted += outcome.converted;
+    }
+    let outcome = kawa.prepare(&mut converter);
+    assert_eq!(
+        outcome,
+        PrepareOutcome {
+            converted: 1,
+            paused: false
+        }
+    );
+    assert_eq!(converted + outcome.converted, total);
+    assert!(kawa.blocks.is_empty());
+}
@@ -1,3 +1,11 @@
+// the benchmarks predate the clippy lints of the test targets and are kept as written
+#![allow(
+    clippy::assertions_on_constants,
+    clippy::redundant_static_lifetimes,
+    clippy::unit_arg,
+    clippy::unused_io_amount
+)]
+
 use std::{hint::black_box, io::Write};
 
 use kawa::{h1, Buffer, Kawa, Kind, SliceBuffer};
@@ -59,3 +67,216 @@ Connection: close\r\n\r\n";
         }
     }
 }
+
+fn fragmented_status_line(kind: Kind, message: &[u8]) {
+    let status_line_end = message
+        .windows(2)
+        .position(|window| window == b"\r\n")
+        .expect("status line")
+        + 2;
+
+    let mut buffer = vec![0; 4096];
+    let mut kawa = Kawa::new(kind, Buffer::new(SliceBuffer(&mut buffer[..])));
+    kawa.storage.write_all(message).expect("write");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    assert!(kawa.is_main_phase());
+    let expected = kawa.debug("").expect("debug");
+
+    for split in 1..=status_line_end {
+        let mut buffer = vec![0; 4096];
+        let mut kawa = Kawa::new(kind, Buffer::new(SliceBuffer(&mut buffer[..])));
+        kawa.storage.write_all(&message[..split]).expect("write");
+        h1::parse(&mut kawa, &mut h1::NoCallbacks);
+        assert!(!kawa.is_error(), "error with first fragment {split}");
+        kawa.storage.write_all(&message[split..]).expect("write");
+        h1::parse(&mut kawa, &mut h1::NoCallbacks);
+        assert_eq!(kawa.debug("").expect("debug"), expected, "split at {split}");
+    }
+
+    let mut buffer = vec![0; 4096];
+    let mut kawa = Kawa::new(kind, Buffer::new(SliceBuffer(&mut buffer[..])));
+    for char in &message[..status_line_end] {
+        kawa.storage.write_all(&[*char]).expect("write");
+        h1::parse(&mut kawa, &mut h1::NoCallbacks);
+        assert!(!kawa.is_error());
+    }
+    kawa.storage
+        .write_all(&message[status_line_end..])
+        .expect("write");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    assert_eq!(kawa.debug("").expect("debug"), expected);
+}
+
+#[test]
+fn fragmented_request_line() {
+    fragmented_status_line(
+        Kind::Request,
+        b"GET /index.html?q=1 HTTP/1.1\r\nHost: www.example.com\r\n\r\n",
+    );
+    fragmented_status_line(
+        Kind::Request,
+        b"POST http://www.example.com:8080/form HTTP/1.0\r\nContent-Length: 0\r\n\r\n",
+    );
+}
+
+#[test]
+fn fragmented_response_line() {
+    fragmented_status_line(
+        Kind::Response,
+        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK",
+    );
+    fragmented_status_line(
+        Kind::Response,
+        b"HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+    );
+    fragmented_status_line(Kind::Response, b"HTTP/1.1 204 \r\n\r\n");
+}
+
+#[test]
+fn buffer_swap() {
+    const REQUEST: &[u8] = b"\
+POST /form HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+Cookie: a=1; b=2\r\n\
+Content-Length: 5\r\n\r\nhello";
+
+    fn parse(kawa: &mut Kawa<Vec<u8>>, data: &[u8]) {
+        kawa.storage.write_all(data).expect("write");
+        h1::parse(kawa, &mut h1::NoCallbacks);
+    }
+
+    for split in 1..REQUEST.len() {
+        let mut kawa = Kawa::new(Kind::Request, Buffer::new(vec![0; 256]));
+        parse(&mut kawa, &REQUEST[..split]);
+        let mut expected = Kawa::new(Kind::Request, Buffer::new(vec![0; 256]));
+        parse(&mut expected, &REQUEST[..split]);
+        parse(&mut expected, &REQUEST[split..]);
+        assert!(expected.is_terminated());
+
+        let state = kawa.resume_state();
+        // the new buffer holds the same bytes at the same offsets
+        let mut swapped = Buffer::new(vec![0; 256]);
+        let end = kawa.storage.end;
+        swapped.buffer[..end].copy_from_slice(&kawa.storage.buffer[..end]);
+        swapped.start = kawa.storage.start;
+        swapped.end = end;
+        kawa.storage = swapped;
+        kawa.restore_state(state);
+        assert_eq!(kawa.resume_state(), state);
+
+        parse(&mut kawa, &REQUEST[split..]);
+        assert_eq!(
+            kawa.debug("").expect("debug"),
+            expected.debug("").expect("debug"),
+            "split at {split}"
+        );
+    }
+}
+
+#[test]
+fn rebase_onto() {
+    const FIRST: &[u8] = b"GET /first HTTP/1.1\r\nHost: a.com\r\nContent-Length: 0\r\n\r\n";
+    const SECOND: &[u8] = b"\
+POST /second HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+Cookie: a=1; b=2\r\n\
+Content-Length: 5\r\n\r\nhello";
+
+    fn output(kawa: &mut Kawa<Vec<u8>>) -> Vec<u8> {
+        kawa.prepare(&mut h1::BlockConverter);
+        let out = kawa
+            .as_io_slice()
+            .iter()
+            .flat_map(|s| s.to_vec())
+            .collect::<Vec<_>>();
+        kawa.consume(out.len());
+        out
+    }
+
+    let mut kawa = Kawa::new(Kind::Request, Buffer::new(vec![0; 4096]));
+    kawa.storage.write_all(FIRST).expect("write");
+    kawa.storage.write_all(SECOND).expect("write");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    assert!(kawa.is_terminated());
+    output(&mut kawa);
+    kawa.clear();
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    assert!(kawa.is_terminated());
+    let mut expected = kawa.clone();
+
+    // the first request is no longer referenced, only the second one is moved
+    let live = kawa.storage.end - kawa.leftmost_ref();
+    assert_eq!(live, SECOND.len());
+    assert!(kawa.rebase_onto(vec![0; live - 1]).is_none());
+    let old = kawa.rebase_onto(vec![0; live]).expect("should fit");
+    assert_eq!(old.len(), 4096);
+    assert_eq!(kawa.storage.capacity(), live);
+    assert_eq!(kawa.storage.used(), SECOND);
+    assert_eq!(
+        kawa.request_uri_parts(),
+        Some((&b"www.example.com"[..], None, &b"/second"[..]))
+    );
+    assert_eq!(output(&mut kawa), output(&mut expected));
+}
+
+#[test]
+fn start_never_exceeds_head() {
+    const REQUEST: &[u8] = b"\
+POST /upload HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+Content-Length: 10\r\n\r\nhello";
+
+    let mut kawa = Kawa::new(Kind::Request, Buffer::new(vec![0; 256]));
+    kawa.storage.write_all(REQUEST).expect("write");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    kawa.prepare(&mut h1::BlockConverter);
+    // odd state: the body referenced by out is not counted as parsed
+    kawa.storage.head -= 7;
+    let amount: usize = kawa.as_io_slice().iter().map(|s| s.len()).sum();
+    kawa.consume(amount - 5);
+    assert_eq!(kawa.storage.start, kawa.storage.head);
+    assert_eq!(kawa.storage.unparsed_data(), b"\r\nhello");
+
+    // consuming everything from the buffer discards the unparsed data
+    kawa.storage.consume(usize::MAX);
+    assert_eq!(kawa.storage.start, kawa.storage.end);
+    assert_eq!(kawa.storage.head, kawa.storage.start);
+    assert!(kawa.storage.unparsed_data().is_empty());
+    kawa.storage.shift();
+    assert_eq!((kawa.storage.start, kawa.storage.head), (0, 0));
+}
+
+#[test]
+fn read_out() {
+    const REQUEST: &[u8] = b"\
+POST /form HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+Cookie: a=1; b=2\r\n\
+Content-Length: 5\r\n\r\nhello";
+
+    let mut kawa = Kawa::new(Kind::Request, Buffer::new(vec![0; 256]));
+    kawa.storage.write_all(REQUEST).expect("write");
+    h1::parse(&mut kawa, &mut h1::NoCallbacks);
+    assert!(kawa.is_terminated());
+    let mut expected = kawa.clone();
+    expected.prepare(&mut h1::BlockConverter);
+    let expected = expected
+        .as_io_slice()
+        .iter()
+        .flat_map(|s| s.to_vec())
+        .collect::<Vec<_>>();
+
+    kawa.prepare(&mut h1::BlockConverter);
+    let mut output = Vec::new();
+    let mut dst = [0; 7];
+    loop {
+        let count = kawa.read_out(&mut dst);
+        if count == 0 {
+            break;
+        }
+        output.extend_from_slice(&dst[..count]);
+    }
+    assert_eq!(output, expected);
+    assert!(kawa.is_completed());
+    assert_eq!(kawa.read_out(&mut dst), 0);
+}
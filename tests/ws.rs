@@ -0,0 +1,44 @@
+use kawa::{ws, Block, Buffer, Chunk, Kawa, Kind, OutBlock, SliceBuffer, Store};
+
+fn convert(payload: &[u8]) -> Vec<u8> {
+    let mut buffer = [0; 16];
+    let mut kawa = Kawa::new(Kind::Response, Buffer::new(SliceBuffer(&mut buffer[..])));
+    kawa.push_block(Block::Chunk(Chunk {
+        data: Store::from_slice(payload),
+    }));
+    kawa.prepare(&mut ws::BlockConverter);
+    let buf = kawa.storage.buffer();
+    let mut out = Vec::new();
+    for block in &kawa.out {
+        match block {
+            OutBlock::Store(store) => out.extend_from_slice(store.data(buf)),
+            OutBlock::Delimiter => panic!("unexpected delimiter"),
+        }
+    }
+    out
+}
+
+#[test]
+fn ws_frame_lengths() {
+    let cases: [(usize, &[u8]); 3] = [
+        (10, &[0x82, 10]),
+        (200, &[0x82, 126, 0, 200]),
+        (70000, &[0x82, 127, 0, 0, 0, 0, 0, 1, 0x11, 0x70]),
+    ];
+    for (length, header) in cases {
+        let payload = (0..length).map(|i| i as u8).collect::<Vec<_>>();
+        let frame = convert(&payload);
+        assert_eq!(&frame[..header.len()], header);
+        assert_eq!(&frame[header.len()..], &payload[..]);
+    }
+}
+
+#[test]
+fn ws_length_boundaries() {
+    for (length, header_size) in [(125, 2), (126, 4), (65535, 4), (65536, 10)] {
+        let frame = convert(&vec![0; length]);
+        assert_eq!(frame.len(), header_size + length);
+    }
+    // empty chunks are not framed
+    assert!(convert(b"").is_empty());
+}